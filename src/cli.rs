@@ -0,0 +1,156 @@
+use std::path::PathBuf;
+
+use bevy::prelude::Vec2;
+
+use crate::sequencer::{SequencerPlugin, StartupScene};
+
+pub const USAGE: &str = "\
+Usage: bevy-sequencer [OPTIONS]
+
+Options:
+  --empty               Start without any notes or playheads
+  --project <PATH>      Start with a saved project
+  --playheads <N>       Playheads in a random scene, one per layer
+  --notes <N>           Notes per layer in a random scene
+  --rate <MIN>..<MAX>   Range of random playhead rates, 1 being one pass per loop
+  --note-size <W>x<H>   Size of new notes
+  --seed <N>            Seed for everything random
+  -h, --help            Print this help";
+
+fn parse_pair(text: &str, separator: &str) -> Option<(f32, f32)> {
+    let (first, second) = text.split_once(separator)?;
+    Some((first.trim().parse().ok()?, second.trim().parse().ok()?))
+}
+
+/// Builds the sequencer from command-line arguments, without the program name.
+pub fn parse(args: &[String]) -> Result<SequencerPlugin, String> {
+    let mut plugin = SequencerPlugin::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        let invalid = |value: &str| format!("invalid value for {}: {}", arg, value);
+
+        match arg.as_str() {
+            "--empty" => plugin.startup_scene = StartupScene::Empty,
+            "--project" => plugin.startup_scene = StartupScene::Project(PathBuf::from(value()?)),
+            "--playheads" => {
+                let value = value()?;
+                plugin.random_scene.playheads = value.parse().map_err(|_| invalid(value))?;
+            }
+            "--notes" => {
+                let value = value()?;
+                plugin.random_scene.notes = value.parse().map_err(|_| invalid(value))?;
+            }
+            "--rate" => {
+                let value = value()?;
+                let (min, max) = parse_pair(value, "..")
+                    .filter(|(min, max)| *min > 0. && min <= max)
+                    .ok_or_else(|| invalid(value))?;
+                plugin.random_scene.rate = min..=max;
+            }
+            "--note-size" => {
+                let value = value()?;
+                let (width, height) = parse_pair(value, "x")
+                    .filter(|(width, height)| *width > 0. && *height > 0.)
+                    .ok_or_else(|| invalid(value))?;
+                plugin.random_scene.note_size = Vec2::new(width, height);
+            }
+            "--seed" => {
+                let value = value()?;
+                plugin.seed = Some(value.parse().map_err(|_| invalid(value))?);
+            }
+            _ => return Err(format!("unknown option: {}", arg)),
+        }
+    }
+
+    Ok(plugin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<SequencerPlugin, String> {
+        parse(
+            &args
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>(),
+        )
+    }
+
+    #[test]
+    fn defaults_to_a_random_scene() {
+        let plugin = parse_args(&[]).unwrap();
+
+        assert!(matches!(plugin.startup_scene, StartupScene::Random));
+        assert_eq!(plugin.seed, None);
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let plugin = parse_args(&[
+            "--playheads",
+            "3",
+            "--notes",
+            "12",
+            "--rate",
+            "0.5..1.5",
+            "--note-size",
+            "20x8",
+            "--seed",
+            "42",
+        ])
+        .unwrap();
+
+        assert_eq!(plugin.random_scene.playheads, 3);
+        assert_eq!(plugin.random_scene.notes, 12);
+        assert_eq!(plugin.random_scene.rate, 0.5..=1.5);
+        assert_eq!(plugin.random_scene.note_size, Vec2::new(20., 8.));
+        assert_eq!(plugin.seed, Some(42));
+    }
+
+    #[test]
+    fn last_scene_option_wins() {
+        let plugin = parse_args(&["--empty", "--project", "song.ron"]).unwrap();
+        assert!(
+            matches!(plugin.startup_scene, StartupScene::Project(path) if path.as_os_str() == "song.ron")
+        );
+
+        let plugin = parse_args(&["--project", "song.ron", "--empty"]).unwrap();
+        assert!(matches!(plugin.startup_scene, StartupScene::Empty));
+    }
+
+    #[test]
+    fn rejects_missing_values() {
+        assert_eq!(
+            parse_args(&["--seed"]).err(),
+            Some("--seed needs a value".to_string())
+        );
+        assert!(parse_args(&["--project"]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert_eq!(
+            parse_args(&["--notes", "many"]).err(),
+            Some("invalid value for --notes: many".to_string())
+        );
+        assert!(parse_args(&["--playheads", "-1"]).is_err());
+        assert!(parse_args(&["--rate", "2..1"]).is_err());
+        assert!(parse_args(&["--rate", "0..1"]).is_err());
+        assert!(parse_args(&["--rate", "1-2"]).is_err());
+        assert!(parse_args(&["--note-size", "0x8"]).is_err());
+        assert!(parse_args(&["--note-size", "20"]).is_err());
+        assert!(parse_args(&["--seed", "1.5"]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert_eq!(
+            parse_args(&["--loud"]).err(),
+            Some("unknown option: --loud".to_string())
+        );
+    }
+}
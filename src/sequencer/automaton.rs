@@ -0,0 +1,509 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContexts};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    keymap::{Action, Keymap},
+    note::{semitone_height, spawn_note},
+    playhead::NoteOffEvent,
+    random::SequencerRng,
+    scale::note_name,
+    sequence::{transport_clock, Canvas, GlobalSequencerSettings, Transport},
+};
+
+const AUTOMATON_SIZE_X: usize = 16;
+const AUTOMATON_SIZE_Y: usize = 16;
+const MAX_AUTOMATON_SIZE: usize = 128;
+
+pub struct AutomatonPlugin;
+
+impl Plugin for AutomatonPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Automaton>()
+            .add_system(advance_automaton.after(transport_clock))
+            .add_system(automaton_notes.after(advance_automaton))
+            .add_system(automaton_window);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutomatonRule {
+    /// Conway's Game of Life, B3/S23.
+    #[default]
+    Life,
+    /// B36/S23.
+    HighLife,
+    /// B2/S.
+    Seeds,
+    /// B3678/S34678.
+    DayAndNight,
+    /// A one-dimensional rule, numbered after Wolfram. Each generation is a new top row,
+    /// older ones scroll down.
+    Elementary(u8),
+}
+
+impl AutomatonRule {
+    pub const ALL: [AutomatonRule; 5] = [
+        AutomatonRule::Life,
+        AutomatonRule::HighLife,
+        AutomatonRule::Seeds,
+        AutomatonRule::DayAndNight,
+        AutomatonRule::Elementary(30),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AutomatonRule::Life => "Game of Life",
+            AutomatonRule::HighLife => "HighLife",
+            AutomatonRule::Seeds => "Seeds",
+            AutomatonRule::DayAndNight => "Day & Night",
+            AutomatonRule::Elementary(_) => "Elementary",
+        }
+    }
+
+    /// Neighbour counts bringing a dead cell to life and keeping a live one alive.
+    fn birth_survival(&self) -> (&'static [usize], &'static [usize]) {
+        match self {
+            AutomatonRule::Life => (&[3], &[2, 3]),
+            AutomatonRule::HighLife => (&[3, 6], &[2, 3]),
+            AutomatonRule::Seeds => (&[2], &[]),
+            AutomatonRule::DayAndNight => (&[3, 6, 7, 8], &[3, 4, 6, 7, 8]),
+            AutomatonRule::Elementary(_) => (&[], &[]),
+        }
+    }
+}
+
+/// A cellular automaton whose live cells are notes on the canvas, one column per step
+/// across the loop and one row per pitch lane. It advances once per bar of the
+/// transport, whatever the rates of the playheads sweeping it. Saved whole with the project, generation included.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct Automaton {
+    pub enabled: bool,
+    pub rule: AutomatonRule,
+    /// Columns and rows.
+    pub size: (usize, usize),
+    /// Row-major, the bottom row first.
+    cells: Vec<bool>,
+    pub generation: u64,
+    /// The same seed and density always start the same pattern.
+    pub seed: u64,
+    /// Chance of a cell starting out alive.
+    pub density: f32,
+    /// Pitch of the bottom row.
+    pub lowest_pitch: u8,
+    pub layer: usize,
+}
+
+impl Default for Automaton {
+    fn default() -> Self {
+        let mut automaton = Automaton {
+            enabled: false,
+            rule: AutomatonRule::default(),
+            size: (AUTOMATON_SIZE_X, AUTOMATON_SIZE_Y),
+            cells: Vec::new(),
+            generation: 0,
+            seed: 0,
+            density: 0.3,
+            lowest_pitch: 60,
+            layer: 0,
+        };
+        automaton.reset();
+        automaton
+    }
+}
+
+impl Automaton {
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.cells[y * self.size.0 + x]
+    }
+
+    /// Takes over a saved automaton, starting it afresh from its seed if its cells
+    /// don't match its size.
+    pub fn restore(&mut self, saved: Automaton) {
+        *self = saved;
+        if self.cells.len() != self.size.0 * self.size.1 {
+            self.reset();
+        }
+    }
+
+    /// Back to generation 0, filled from the seed.
+    pub fn reset(&mut self) {
+        let (columns, rows) = self.size;
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        self.cells = vec![false; columns * rows];
+        self.generation = 0;
+
+        // An elementary automaton grows from its top row only.
+        let seeded_rows = match self.rule {
+            AutomatonRule::Elementary(_) => rows - 1..rows,
+            _ => 0..rows,
+        };
+        for y in seeded_rows {
+            for x in 0..columns {
+                self.cells[y * columns + x] = rng.gen::<f32>() < self.density;
+            }
+        }
+    }
+
+    /// Live neighbours of a cell, wrapping around the edges.
+    fn neighbours(&self, x: usize, y: usize) -> usize {
+        let (columns, rows) = self.size;
+        let mut count = 0;
+        for dy in [rows - 1, 0, 1] {
+            for dx in [columns - 1, 0, 1] {
+                if (dx, dy) != (0, 0) && self.get((x + dx) % columns, (y + dy) % rows) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    pub fn step(&mut self) {
+        let (columns, rows) = self.size;
+        let mut cells = vec![false; columns * rows];
+
+        match self.rule {
+            AutomatonRule::Elementary(rule) => {
+                cells[..(rows - 1) * columns].copy_from_slice(&self.cells[columns..]);
+                let top = rows - 1;
+                for x in 0..columns {
+                    let left = self.get((x + columns - 1) % columns, top) as u8;
+                    let centre = self.get(x, top) as u8;
+                    let right = self.get((x + 1) % columns, top) as u8;
+                    let pattern = left << 2 | centre << 1 | right;
+                    cells[top * columns + x] = rule >> pattern & 1 == 1;
+                }
+            }
+            rule => {
+                let (birth, survival) = rule.birth_survival();
+                for y in 0..rows {
+                    for x in 0..columns {
+                        let neighbours = self.neighbours(x, y);
+                        cells[y * columns + x] = if self.get(x, y) {
+                            survival.contains(&neighbours)
+                        } else {
+                            birth.contains(&neighbours)
+                        };
+                    }
+                }
+            }
+        }
+
+        self.cells = cells;
+        self.generation += 1;
+    }
+}
+
+/// A note standing for the automaton cell at these coordinates.
+#[derive(Component)]
+pub struct AutomatonCell(pub usize, pub usize);
+
+/// Steps the automaton whenever the transport enters another bar. Stop counts from the
+/// first bar again without stepping.
+fn advance_automaton(
+    transport: Res<Transport>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    mut automaton: ResMut<Automaton>,
+    mut last_bar: Local<Option<u64>>,
+) {
+    if !automaton.enabled {
+        *last_bar = None;
+        return;
+    }
+
+    let bar = (transport.beats / sequencer_settings.beats_per_bar as f64).floor() as u64;
+    if last_bar.is_some_and(|last_bar| bar > last_bar) {
+        automaton.step();
+    }
+    *last_bar = Some(bar);
+}
+
+/// Keeps a note on every live cell: dying cells are released and removed, newborn ones
+/// spawned. Surviving cells keep their notes.
+pub fn automaton_notes(
+    mut commands: Commands,
+    automaton: Res<Automaton>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    cell_query: Query<(Entity, &AutomatonCell)>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+    mut placement: Local<Option<(u8, usize)>>,
+) {
+    if !automaton.is_changed() && !canvas.is_changed() && !sequencer_settings.is_changed() {
+        return;
+    }
+
+    let (columns, rows) = automaton.size;
+    let mut existing = HashMap::new();
+    for (entity, cell) in cell_query.iter() {
+        existing.insert((cell.0, cell.1), entity);
+    }
+
+    // Layout changes move every cell, so start over.
+    let new_placement = Some((automaton.lowest_pitch, automaton.layer));
+    let relayout =
+        canvas.is_changed() || sequencer_settings.is_changed() || *placement != new_placement;
+    *placement = new_placement;
+    let cell_width = canvas.width / columns as f32;
+    let lane_height = semitone_height(canvas.height, &sequencer_settings);
+    let lowest_lane = automaton.lowest_pitch as f32 - sequencer_settings.pitch_min as f32;
+
+    for y in 0..rows {
+        for x in 0..columns {
+            let alive = automaton.enabled && automaton.get(x, y);
+            let entity = existing.remove(&(x, y));
+
+            if let Some(entity) = entity {
+                if alive && !relayout {
+                    continue;
+                }
+                midi_out_note_off.send(NoteOffEvent(entity));
+                commands.entity(entity).despawn_recursive();
+            }
+
+            if alive {
+                let translation = Vec3::new(
+                    (x as f32 + 0.5) * cell_width,
+                    (lowest_lane + y as f32 + 0.5) * lane_height,
+                    automaton.layer as f32,
+                );
+                let note = spawn_note(
+                    &mut commands,
+                    translation,
+                    Vec2::new(cell_width, lane_height),
+                );
+                commands.entity(note).insert(AutomatonCell(x, y));
+            }
+        }
+    }
+
+    // Cells left over from a larger grid.
+    for entity in existing.into_values() {
+        midi_out_note_off.send(NoteOffEvent(entity));
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Toggled with L by default.
+fn automaton_window(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut open: Local<bool>,
+    mut automaton: ResMut<Automaton>,
+    mut sequencer_rng: ResMut<SequencerRng>,
+) {
+    let ctx = contexts.ctx_mut();
+    if !ctx.wants_keyboard_input() && keymap.just_pressed(Action::ToggleAutomaton, &keyboard_input)
+    {
+        *open = !*open;
+    }
+
+    let mut window_open = *open;
+    egui::Window::new("Automaton")
+        .open(&mut window_open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let mut enabled = automaton.enabled;
+            let mut rule = automaton.rule;
+            let (mut columns, mut rows) = automaton.size;
+            let mut seed = automaton.seed;
+            let mut density = automaton.density;
+            let mut lowest_pitch = automaton.lowest_pitch;
+            let mut layer = automaton.layer;
+
+            ui.checkbox(&mut enabled, "Enabled");
+            egui::Grid::new("automaton_settings").show(ui, |ui| {
+                ui.label("Rule");
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("automaton_rule")
+                        .selected_text(rule.name())
+                        .show_ui(ui, |ui| {
+                            for option in AutomatonRule::ALL {
+                                let selected = std::mem::discriminant(&rule)
+                                    == std::mem::discriminant(&option);
+                                if ui.selectable_label(selected, option.name()).clicked()
+                                    && !selected
+                                {
+                                    rule = option;
+                                }
+                            }
+                        });
+                    if let AutomatonRule::Elementary(number) = &mut rule {
+                        ui.add(egui::DragValue::new(number));
+                    }
+                });
+                ui.end_row();
+                ui.label("Size");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut columns).clamp_range(3..=MAX_AUTOMATON_SIZE));
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut rows).clamp_range(3..=MAX_AUTOMATON_SIZE));
+                });
+                ui.end_row();
+                ui.label("Lowest pitch");
+                ui.add(
+                    egui::DragValue::new(&mut lowest_pitch)
+                        .clamp_range(0..=127)
+                        .custom_formatter(|pitch, _| note_name(pitch as u8)),
+                );
+                ui.end_row();
+                ui.label("Layer");
+                ui.add(egui::DragValue::new(&mut layer));
+                ui.end_row();
+                ui.label("Seed");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut seed));
+                    if ui.button("Random").clicked() {
+                        seed = sequencer_rng.next_u64();
+                    }
+                });
+                ui.end_row();
+                ui.label("Density");
+                ui.add(egui::Slider::new(&mut density, 0.0..=1.0));
+                ui.end_row();
+            });
+
+            let restart = rule != automaton.rule
+                || (columns, rows) != automaton.size
+                || seed != automaton.seed
+                || density != automaton.density;
+            if restart {
+                automaton.rule = rule;
+                automaton.size = (columns, rows);
+                automaton.seed = seed;
+                automaton.density = density;
+                automaton.reset();
+            }
+            if enabled != automaton.enabled {
+                automaton.enabled = enabled;
+            }
+            if lowest_pitch != automaton.lowest_pitch || layer != automaton.layer {
+                automaton.lowest_pitch = lowest_pitch;
+                automaton.layer = layer;
+            }
+
+            ui.horizontal(|ui| {
+                ui.label(format!("Generation {}", automaton.generation));
+                if ui.button("Step").clicked() {
+                    automaton.step();
+                }
+                if ui.button("Reset").clicked() {
+                    automaton.reset();
+                }
+            });
+        });
+    *open = window_open;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn automaton(rule: AutomatonRule, size: (usize, usize), live: &[(usize, usize)]) -> Automaton {
+        let mut automaton = Automaton {
+            rule,
+            size,
+            ..default()
+        };
+        automaton.cells = vec![false; size.0 * size.1];
+        for (x, y) in live {
+            automaton.cells[y * size.0 + x] = true;
+        }
+        automaton
+    }
+
+    fn live(automaton: &Automaton) -> Vec<(usize, usize)> {
+        let (columns, rows) = automaton.size;
+        (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x, y)))
+            .filter(|(x, y)| automaton.get(*x, *y))
+            .collect()
+    }
+
+    fn shifted(cells: &[(usize, usize)], by: usize, size: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut cells = cells
+            .iter()
+            .map(|(x, y)| ((x + by) % size.0, (y + by) % size.1))
+            .collect::<Vec<_>>();
+        cells.sort_by_key(|(x, y)| (*y, *x));
+        cells
+    }
+
+    #[test]
+    fn blinker_oscillates() {
+        let horizontal = [(1, 2), (2, 2), (3, 2)];
+        let mut automaton = automaton(AutomatonRule::Life, (5, 5), &horizontal);
+
+        automaton.step();
+        assert_eq!(live(&automaton), vec![(2, 1), (2, 2), (2, 3)]);
+        automaton.step();
+        assert_eq!(live(&automaton), horizontal);
+        assert_eq!(automaton.generation, 2);
+    }
+
+    #[test]
+    fn glider_moves_diagonally() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let size = (8, 8);
+        let mut automaton = automaton(AutomatonRule::Life, size, &glider);
+
+        for _ in 0..4 {
+            automaton.step();
+        }
+        assert_eq!(live(&automaton), shifted(&glider, 1, size));
+    }
+
+    #[test]
+    fn glider_wraps_around_the_edges() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let size = (6, 6);
+        let mut automaton = automaton(AutomatonRule::Life, size, &glider);
+
+        // Partway across an edge, then all the way round.
+        for _ in 0..16 {
+            automaton.step();
+        }
+        assert_eq!(live(&automaton), shifted(&glider, 4, size));
+        for _ in 0..8 {
+            automaton.step();
+        }
+        assert_eq!(live(&automaton), shifted(&glider, 0, size));
+    }
+
+    #[test]
+    fn neighbours_wrap_around_the_edges() {
+        let automaton = automaton(
+            AutomatonRule::Life,
+            (4, 4),
+            &[(3, 3), (3, 0), (0, 3), (2, 2)],
+        );
+
+        assert_eq!(automaton.neighbours(0, 0), 3);
+        assert_eq!(automaton.neighbours(3, 3), 3);
+        assert_eq!(automaton.neighbours(1, 1), 1);
+    }
+
+    #[test]
+    fn rule_30_grows_a_new_top_row() {
+        let mut automaton = automaton(AutomatonRule::Elementary(30), (7, 3), &[(3, 2)]);
+
+        automaton.step();
+        assert_eq!(live(&automaton), vec![(3, 1), (2, 2), (3, 2), (4, 2)]);
+        automaton.step();
+        assert_eq!(
+            live(&automaton),
+            vec![(3, 0), (2, 1), (3, 1), (4, 1), (1, 2), (2, 2), (5, 2)]
+        );
+    }
+
+    #[test]
+    fn elementary_rows_wrap_around_the_edges() {
+        let mut automaton = automaton(AutomatonRule::Elementary(30), (5, 2), &[(0, 1)]);
+
+        automaton.step();
+        assert_eq!(live(&automaton), vec![(0, 0), (0, 1), (1, 1), (4, 1)]);
+    }
+}
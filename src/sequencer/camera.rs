@@ -0,0 +1,206 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+
+use super::{
+    keymap::{Action, Keymap},
+    layer::{layer_of, ActiveLayer},
+    playhead::Playhead,
+    sequence::Canvas,
+};
+
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 10.0;
+/// Zoom factor applied per line scrolled.
+const ZOOM_STEP: f32 = 1.1;
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraSettings>()
+            .init_resource::<Panning>()
+            .add_startup_system(spawn_camera)
+            .add_system(pan_camera.in_base_set(CoreSet::PreUpdate))
+            .add_system(zoom_camera)
+            .add_system(zoom_keys)
+            .add_system(toggle_follow_playhead)
+            .add_system(follow_playhead.after(zoom_camera));
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct CameraSettings {
+    /// Keep the active layer's playhead in the middle of the view.
+    pub follow_playhead: bool,
+}
+
+/// Set while the middle button or space is held, when dragging pans instead of editing.
+#[derive(Resource, Default, Debug)]
+pub struct Panning(pub bool);
+
+/// Where the camera's viewport starts in the window, bottom-left like the cursor position.
+/// Panels take up the rest of the window.
+pub fn viewport_origin(camera: &Camera, window: &Window) -> Vec2 {
+    camera
+        .logical_viewport_rect()
+        .map(|(min, max)| Vec2::new(min.x, window.height() - max.y))
+        .unwrap_or(Vec2::ZERO)
+}
+
+/// Starts out showing the whole canvas.
+pub fn spawn_camera(
+    mut commands: Commands,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    canvas: Res<Canvas>,
+) {
+    let window = window_query.get_single().unwrap();
+    let scale = (canvas.width / window.width()).max(canvas.height / window.height());
+
+    commands.spawn(Camera2dBundle {
+        transform: Transform::from_xyz(canvas.width / 2.0, canvas.height / 2.0, 100.0),
+        projection: OrthographicProjection { scale, ..default() },
+        ..default()
+    });
+}
+
+fn pan_camera(
+    mut contexts: EguiContexts,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut panning: ResMut<Panning>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<Camera2d>>,
+) {
+    let ctx = contexts.ctx_mut();
+    let space = !ctx.wants_keyboard_input() && keyboard_input.pressed(KeyCode::Space);
+    panning.0 = space || mouse_button_input.pressed(MouseButton::Middle);
+
+    let dragging = mouse_button_input.pressed(MouseButton::Middle)
+        || (space && mouse_button_input.pressed(MouseButton::Left));
+    let delta: Vec2 = mouse_motion_events.iter().map(|event| event.delta).sum();
+
+    if !dragging || ctx.is_using_pointer() || delta == Vec2::ZERO {
+        return;
+    }
+
+    let (mut transform, projection) = camera_query.single_mut();
+    // Mouse motion is in window pixels with y pointing down.
+    transform.translation.x -= delta.x * projection.scale;
+    transform.translation.y += delta.y * projection.scale;
+}
+
+fn zoom_camera(
+    mut contexts: EguiContexts,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&Camera, &mut Transform, &mut OrthographicProjection), With<Camera2d>>,
+) {
+    let lines: f32 = mouse_wheel_events
+        .iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_SCROLL_LINE,
+        })
+        .sum();
+
+    if lines == 0. || contexts.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+
+    let window = window_query.get_single().unwrap();
+    let (camera, mut transform, mut projection) = camera_query.single_mut();
+    zoom(
+        camera,
+        window,
+        &mut transform,
+        &mut projection,
+        lines,
+        window.cursor_position(),
+    );
+}
+
+/// Zooms by `lines` scroll lines, keeping the point at `focus`, in window coordinates,
+/// in place. Without a focus the middle of the view stays.
+fn zoom(
+    camera: &Camera,
+    window: &Window,
+    transform: &mut Transform,
+    projection: &mut OrthographicProjection,
+    lines: f32,
+    focus: Option<Vec2>,
+) {
+    let old_scale = projection.scale;
+    projection.scale = (old_scale * ZOOM_STEP.powf(-lines)).clamp(MIN_ZOOM, MAX_ZOOM);
+
+    if let (Some(focus), Some(viewport_size)) = (focus, camera.logical_viewport_size()) {
+        let from_center = focus - viewport_origin(camera, window) - viewport_size / 2.;
+        let offset = from_center * (old_scale - projection.scale);
+        transform.translation += offset.extend(0.);
+    }
+}
+
+fn zoom_keys(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&Camera, &mut Transform, &mut OrthographicProjection), With<Camera2d>>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    let mut lines = 0.;
+    if keymap.just_pressed(Action::ZoomIn, &keyboard_input) {
+        lines += 1.;
+    }
+    if keymap.just_pressed(Action::ZoomOut, &keyboard_input) {
+        lines -= 1.;
+    }
+    if lines == 0. {
+        return;
+    }
+
+    let window = window_query.get_single().unwrap();
+    let (camera, mut transform, mut projection) = camera_query.single_mut();
+    zoom(camera, window, &mut transform, &mut projection, lines, None);
+}
+
+fn toggle_follow_playhead(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut camera_settings: ResMut<CameraSettings>,
+) {
+    if !contexts.ctx_mut().wants_keyboard_input()
+        && keymap.just_pressed(Action::FollowPlayhead, &keyboard_input)
+    {
+        camera_settings.follow_playhead = !camera_settings.follow_playhead;
+        info!("follow playhead: {}", camera_settings.follow_playhead);
+    }
+}
+
+fn follow_playhead(
+    camera_settings: Res<CameraSettings>,
+    active_layer: Res<ActiveLayer>,
+    playhead_query: Query<&Transform, (With<Playhead>, Without<Camera2d>)>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    if !camera_settings.follow_playhead {
+        return;
+    }
+
+    let playhead = playhead_query
+        .iter()
+        .find(|transform| layer_of(transform) == active_layer.0)
+        .or_else(|| playhead_query.iter().next());
+
+    if let Some(playhead_transform) = playhead {
+        camera_query.single_mut().translation.x = playhead_transform.translation.x;
+    }
+}
@@ -0,0 +1,215 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_egui::{EguiClipboard, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    grid::Snap,
+    keymap::{Action, Keymap},
+    layer::{layer_of, ActiveLayer},
+    mouse_input::{delete_note, CursorPosition, Selected},
+    note::Note,
+    playhead::{NoteOffEvent, Playhead},
+    project::{ProjectNote, ProjectPlayhead},
+    sequence::Canvas,
+};
+
+pub struct ClipboardPlugin;
+
+impl Plugin for ClipboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Clipboard>()
+            .add_event::<CopyPlayhead>()
+            .add_system(copy_selected_notes)
+            .add_system(copy_playhead)
+            .add_system(paste.after(copy_selected_notes));
+    }
+}
+
+/// Puts one playhead on the clipboard, from the control panel.
+pub struct CopyPlayhead(pub Entity);
+
+/// The last thing copied, for when the system clipboard isn't available.
+#[derive(Resource, Default, Debug)]
+struct Clipboard {
+    text: Option<String>,
+}
+
+/// What gets copied, as text so it can be pasted into another project. Positions
+/// are relative to the leftmost note start or playhead, pitches and layers are kept.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ClipboardContents {
+    notes: Vec<ProjectNote>,
+    playheads: Vec<ProjectPlayhead>,
+}
+
+impl ClipboardContents {
+    fn new(notes: Vec<ProjectNote>, playheads: Vec<ProjectPlayhead>) -> Self {
+        let mut contents = ClipboardContents { notes, playheads };
+        let anchor = contents.left();
+        contents.shift(-anchor);
+        contents
+    }
+
+    fn left(&self) -> f32 {
+        self.notes
+            .iter()
+            .map(|note| note.position.0 - note.size.0 / 2.)
+            .chain(self.playheads.iter().map(|playhead| playhead.x))
+            .reduce(f32::min)
+            .unwrap_or(0.)
+    }
+
+    fn shift(&mut self, x: f32) {
+        for note in self.notes.iter_mut() {
+            note.position.0 += x;
+        }
+        for playhead in self.playheads.iter_mut() {
+            playhead.x += x;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.notes.is_empty() && self.playheads.is_empty()
+    }
+}
+
+fn store(
+    contents: &ClipboardContents,
+    clipboard: &mut Clipboard,
+    egui_clipboard: &mut EguiClipboard,
+) {
+    match ron::ser::to_string_pretty(contents, ron::ser::PrettyConfig::default()) {
+        Ok(text) => {
+            egui_clipboard.set_contents(&text);
+            clipboard.text = Some(text);
+        }
+        Err(error) => error!("couldn't copy: {}", error),
+    }
+}
+
+/// The system clipboard when it holds something pasteable, otherwise the last copy.
+fn load(clipboard: &Clipboard, egui_clipboard: &EguiClipboard) -> Option<ClipboardContents> {
+    egui_clipboard
+        .get_contents()
+        .into_iter()
+        .chain(clipboard.text.clone())
+        .find_map(|text| ron::from_str::<ClipboardContents>(&text).ok())
+        .filter(|contents| !contents.is_empty())
+}
+
+/// Ctrl+C copies and Ctrl+X cuts the selected notes, by default.
+#[allow(clippy::too_many_arguments)]
+fn copy_selected_notes(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut selected: ResMut<Selected>,
+    mut clipboard: ResMut<Clipboard>,
+    mut egui_clipboard: ResMut<EguiClipboard>,
+    note_query: Query<(&Note, &Transform)>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let cut = keymap.just_pressed(Action::Cut, &keyboard_input);
+    if !cut && !keymap.just_pressed(Action::Copy, &keyboard_input) {
+        return;
+    }
+
+    let notes = selected
+        .entities
+        .iter()
+        .filter_map(|entity| note_query.get(*entity).ok())
+        .map(|(note, transform)| ProjectNote::new(note, transform, false, None))
+        .collect::<Vec<ProjectNote>>();
+    if notes.is_empty() {
+        return;
+    }
+    store(
+        &ClipboardContents::new(notes, Vec::new()),
+        &mut clipboard,
+        &mut egui_clipboard,
+    );
+
+    if cut {
+        for entity in selected.entities.drain() {
+            delete_note(&mut commands, &mut midi_out_note_off, entity);
+        }
+    }
+}
+
+fn copy_playhead(
+    mut copy_events: EventReader<CopyPlayhead>,
+    mut clipboard: ResMut<Clipboard>,
+    mut egui_clipboard: ResMut<EguiClipboard>,
+    playhead_query: Query<(&Transform, &Playhead)>,
+) {
+    let playheads = copy_events
+        .iter()
+        .filter_map(|CopyPlayhead(entity)| playhead_query.get(*entity).ok())
+        .map(|(transform, playhead)| ProjectPlayhead::new(transform, playhead))
+        .collect::<Vec<ProjectPlayhead>>();
+    if playheads.is_empty() {
+        return;
+    }
+
+    store(
+        &ClipboardContents::new(Vec::new(), playheads),
+        &mut clipboard,
+        &mut egui_clipboard,
+    );
+}
+
+/// Ctrl+V pastes at the mouse cursor, Ctrl+Shift+V at the active layer's playhead, by
+/// default. The pasted notes become the selection, leaving out any that would start off
+/// the canvas.
+#[allow(clippy::too_many_arguments)]
+fn paste(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    (cursor, snap): (Res<CursorPosition>, Res<Snap>),
+    canvas: Res<Canvas>,
+    active_layer: Res<ActiveLayer>,
+    mut selected: ResMut<Selected>,
+    clipboard: Res<Clipboard>,
+    egui_clipboard: Res<EguiClipboard>,
+    playhead_query: Query<&Transform, With<Playhead>>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    let x = if keymap.just_pressed(Action::Paste, &keyboard_input) {
+        cursor.world.map(|position| snap.x(position.x))
+    } else if keymap.just_pressed(Action::PasteAtPlayhead, &keyboard_input) {
+        playhead_query
+            .iter()
+            .find(|transform| layer_of(transform) == active_layer.0)
+            .or_else(|| playhead_query.iter().next())
+            .map(|transform| transform.translation.x)
+    } else {
+        return;
+    };
+    let (Some(x), Some(mut contents)) = (x, load(&clipboard, &egui_clipboard)) else {
+        return;
+    };
+
+    contents.shift(x.clamp(0., canvas.width) - contents.left());
+    for playhead in contents.playheads.iter_mut() {
+        playhead.x = playhead.x.clamp(0., canvas.width);
+        playhead.spawn(&mut commands, &canvas);
+    }
+    let pasted = contents
+        .notes
+        .iter()
+        .filter(|note| note.on_canvas(&canvas))
+        .map(|note| note.spawn(&mut commands))
+        .collect::<HashSet<Entity>>();
+    if !pasted.is_empty() {
+        selected.entities = pasted;
+    }
+}
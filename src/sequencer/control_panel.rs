@@ -1,42 +1,142 @@
-use bevy::{prelude::*, window::PrimaryWindow};
-use bevy_egui::{egui, EguiContexts, EguiPlugin};
+use std::collections::BTreeSet;
+
+use bevy::{prelude::*, render::camera::Viewport, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts};
 use bevy_midi::prelude::MidiOutput;
 
+use super::{
+    clipboard::CopyPlayhead,
+    grid::{SnapResolution, SnapSettings},
+    layer::{layer_of, ActiveLayer, Layers},
+    midi::MidiSettings,
+    note::{Note, NoteColorKey, NoteColors},
+    playhead::{spawn_playhead, LoopRegion, Playhead, PlayheadDirection, Rewind, MIN_LOOP_WIDTH},
+    random::{seed_editor, Regenerate, SequencerRng},
+    scale::{note_name, scale_picker},
+    sequence::{Canvas, GlobalSequencerSettings, Transport},
+    tuning::{tuning_editor, LoadedTuning, TuningPaths, TuningSettings},
+};
+
+const MAX_PLAYHEAD_RATE: f32 = 16.0;
+const MAX_LAYER_VELOCITY: f32 = 2.0;
+
 pub struct ControlPanelPlugin;
 
 impl Plugin for ControlPanelPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<OccupiedScreenSpace>()
-            .add_plugin(EguiPlugin)
-            .add_startup_system(setup_system)
-            .add_system(ui_example_system)
-            .add_system(update_camera_transform_system);
+            .add_system(control_panel)
+            .add_system(update_camera_viewport.after(control_panel));
     }
 }
 
+/// Window space covered by panels, in logical pixels, which the canvas view leaves out.
 #[derive(Default, Resource)]
-struct OccupiedScreenSpace {
-    left: f32,
-    top: f32,
-    right: f32,
-    bottom: f32,
+pub struct OccupiedScreenSpace {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
 }
 
-const CAMERA_TARGET: Vec3 = Vec3::ZERO;
-
-#[derive(Resource, Deref, DerefMut)]
-struct OriginalCameraTransform(Transform);
-
-fn ui_example_system(
+#[allow(clippy::too_many_arguments)]
+fn control_panel(
+    mut commands: Commands,
     mut contexts: EguiContexts,
     mut occupied_screen_space: ResMut<OccupiedScreenSpace>,
+    mut transport: ResMut<Transport>,
+    mut sequencer_settings: ResMut<GlobalSequencerSettings>,
+    canvas: Res<Canvas>,
+    output: Res<MidiOutput>,
+    mut midi_settings: ResMut<MidiSettings>,
+    mut playhead_query: Query<(Entity, &mut Transform, &mut Playhead)>,
+    active_layer: Res<ActiveLayer>,
+    mut layers: ResMut<Layers>,
+    note_query: Query<&Transform, (With<Note>, Without<Playhead>)>,
+    mut note_colors: ResMut<NoteColors>,
+    (mut copy_playhead, mut rewinds, mut regenerate): (
+        EventWriter<CopyPlayhead>,
+        EventWriter<Rewind>,
+        EventWriter<Regenerate>,
+    ),
+    (mut snap_settings, mut tuning_settings, loaded_tuning, mut sequencer_rng): (
+        ResMut<SnapSettings>,
+        ResMut<TuningSettings>,
+        Res<LoadedTuning>,
+        ResMut<SequencerRng>,
+    ),
+    (mut custom_intervals, mut tuning_paths): (Local<String>, Local<TuningPaths>),
 ) {
+    // Layers anything is on, plus any with settings.
+    let layers_in_use = note_query
+        .iter()
+        .chain(playhead_query.iter().map(|(_, transform, _)| transform))
+        .map(layer_of)
+        .chain(layers.0.keys().copied())
+        .collect::<BTreeSet<usize>>();
+
     let ctx = contexts.ctx_mut();
 
-    occupied_screen_space.left = egui::SidePanel::left("left_panel")
+    occupied_screen_space.left = egui::SidePanel::left("control_panel")
         .resizable(true)
         .show(ctx, |ui| {
-            ui.label("Left resizeable panel");
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("Transport");
+                transport_controls(
+                    ui,
+                    &mut transport,
+                    &canvas,
+                    &mut playhead_query,
+                    &mut rewinds,
+                );
+
+                ui.separator();
+                ui.heading("Sequence");
+                sequence_settings(ui, &mut sequencer_settings);
+                note_color_picker(ui, &mut note_colors);
+
+                ui.separator();
+                ui.heading("Scale");
+                scale_settings(
+                    ui,
+                    &mut sequencer_settings,
+                    &mut layers,
+                    &active_layer,
+                    &mut custom_intervals,
+                );
+
+                ui.separator();
+                ui.heading("Snap");
+                snap_editor(ui, &mut snap_settings);
+
+                ui.separator();
+                ui.heading("Tuning");
+                tuning_editor(ui, &mut tuning_settings, &loaded_tuning, &mut tuning_paths);
+
+                ui.separator();
+                ui.heading("MIDI");
+                midi_ports(ui, &output, &mut midi_settings);
+
+                ui.separator();
+                ui.heading("Playheads");
+                playhead_settings(
+                    ui,
+                    &mut commands,
+                    &canvas,
+                    &sequencer_settings,
+                    &active_layer,
+                    &mut playhead_query,
+                    &mut copy_playhead,
+                );
+
+                ui.separator();
+                ui.heading("Layers");
+                layer_mixer(ui, &mut layers, &layers_in_use);
+
+                ui.separator();
+                ui.heading("Random");
+                seed_editor(ui, &mut sequencer_rng, &mut regenerate);
+            });
             ui.allocate_rect(ui.available_rect_before_wrap(), egui::Sense::hover());
         })
         .response
@@ -44,80 +144,389 @@ fn ui_example_system(
         .width();
 }
 
-fn setup_system(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+fn transport_controls(
+    ui: &mut egui::Ui,
+    transport: &mut Transport,
+    canvas: &Canvas,
+    playhead_query: &mut Query<(Entity, &mut Transform, &mut Playhead)>,
+    rewinds: &mut EventWriter<Rewind>,
 ) {
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Plane {
-            size: 5.0,
-            subdivisions: 0,
-        })),
-        material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
-        ..Default::default()
+    ui.horizontal(|ui| {
+        let label = if transport.playing { "Pause" } else { "Play" };
+        if ui.button(label).clicked() {
+            transport.playing = !transport.playing;
+        }
+        if ui.button("Stop").clicked() {
+            transport.playing = false;
+            for (_, mut transform, mut playhead) in playhead_query.iter_mut() {
+                playhead.rewind(&mut transform, canvas);
+            }
+            rewinds.send(Rewind);
+        }
     });
-    commands.spawn(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Cube { size: 1.0 })),
-        material: materials.add(Color::rgb(0.8, 0.7, 0.6).into()),
-        transform: Transform::from_xyz(0.0, 0.5, 0.0),
-        ..Default::default()
+
+    let mut tempo = transport.tempo;
+    ui.add(
+        egui::Slider::new(&mut tempo, 20.0..=300.0)
+            .text("Tempo")
+            .suffix(" BPM"),
+    )
+    .on_hover_text("Playheads keep their rates, so their speeds follow it");
+    if tempo != transport.tempo {
+        transport.tempo = tempo;
+    }
+}
+
+fn sequence_settings(ui: &mut egui::Ui, sequencer_settings: &mut GlobalSequencerSettings) {
+    let mut pitch_min = sequencer_settings.pitch_min;
+    let mut pitch_max = sequencer_settings.pitch_max;
+    let mut bars_per_loop = sequencer_settings.bars_per_loop;
+    let mut beats_per_bar = sequencer_settings.beats_per_bar;
+
+    egui::Grid::new("sequence_settings").show(ui, |ui| {
+        ui.label("Lowest pitch");
+        ui.add(
+            egui::DragValue::new(&mut pitch_min)
+                .clamp_range(0..=pitch_max - 1)
+                .custom_formatter(|pitch, _| note_name(pitch as u8)),
+        );
+        ui.end_row();
+        ui.label("Highest pitch");
+        ui.add(
+            egui::DragValue::new(&mut pitch_max)
+                .clamp_range(pitch_min + 1..=127)
+                .custom_formatter(|pitch, _| note_name(pitch as u8)),
+        );
+        ui.end_row();
+        ui.label("Bars per loop");
+        ui.add(egui::DragValue::new(&mut bars_per_loop).clamp_range(1..=64));
+        ui.end_row();
+        ui.label("Beats per bar");
+        ui.add(egui::DragValue::new(&mut beats_per_bar).clamp_range(1..=16));
+        ui.end_row();
     });
-    commands.spawn(PointLightBundle {
-        point_light: PointLight {
-            intensity: 1500.0,
-            shadows_enabled: true,
-            ..Default::default()
-        },
-        transform: Transform::from_xyz(4.0, 8.0, 4.0),
-        ..Default::default()
+
+    if (pitch_min, pitch_max, bars_per_loop, beats_per_bar)
+        != (
+            sequencer_settings.pitch_min,
+            sequencer_settings.pitch_max,
+            sequencer_settings.bars_per_loop,
+            sequencer_settings.beats_per_bar,
+        )
+    {
+        sequencer_settings.pitch_min = pitch_min;
+        sequencer_settings.pitch_max = pitch_max;
+        sequencer_settings.bars_per_loop = bars_per_loop;
+        sequencer_settings.beats_per_bar = beats_per_bar;
+    }
+}
+
+/// The global scale, and the active layer's own if it overrides it.
+fn scale_settings(
+    ui: &mut egui::Ui,
+    sequencer_settings: &mut GlobalSequencerSettings,
+    layers: &mut Layers,
+    active_layer: &ActiveLayer,
+    custom_intervals: &mut String,
+) {
+    let mut scale = sequencer_settings.scale.clone();
+    let mut root = sequencer_settings.root;
+    scale_picker(ui, &mut scale, &mut root, custom_intervals);
+
+    if scale != sequencer_settings.scale || root != sequencer_settings.root {
+        sequencer_settings.scale = scale;
+        sequencer_settings.root = root;
+    }
+
+    let layer = layers.get(active_layer.0);
+    let mut override_scale = layer.scale.is_some();
+    egui::CollapsingHeader::new(format!("Layer {} scale", active_layer.0))
+        .id_source("layer_scale")
+        .show(ui, |ui| {
+            ui.checkbox(&mut override_scale, "Override global scale");
+
+            let (mut scale, mut root) = if override_scale {
+                let (scale, root) = sequencer_settings.scale_for(&layer);
+                (Some(scale.clone()), Some(root))
+            } else {
+                (None, None)
+            };
+            if let (Some(scale), Some(root)) = (&mut scale, &mut root) {
+                scale_picker(ui, scale, root, custom_intervals);
+            }
+
+            if scale != layer.scale || root != layer.root {
+                let layer = layers.get_mut(active_layer.0);
+                layer.scale = scale;
+                layer.root = root;
+            }
+        });
+}
+
+fn snap_editor(ui: &mut egui::Ui, snap_settings: &mut SnapSettings) {
+    let mut resolution = snap_settings.resolution;
+    let mut pitch = snap_settings.pitch;
+    ui.horizontal_wrapped(|ui| {
+        for option in SnapResolution::ALL {
+            ui.radio_value(&mut resolution, option, option.name());
+        }
     });
+    ui.checkbox(&mut pitch, "Snap to pitch lanes")
+        .on_hover_text("Hold Alt to bypass snapping");
 
-    let camera_pos = Vec3::new(-2.0, 2.5, 5.0);
-    let camera_transform =
-        Transform::from_translation(camera_pos).looking_at(CAMERA_TARGET, Vec3::Y);
-    commands.insert_resource(OriginalCameraTransform(camera_transform));
+    if resolution != snap_settings.resolution || pitch != snap_settings.pitch {
+        snap_settings.resolution = resolution;
+        snap_settings.pitch = pitch;
+    }
+}
 
-    commands.spawn(Camera3dBundle {
-        transform: camera_transform,
-        ..Default::default()
+fn note_color_picker(ui: &mut egui::Ui, note_colors: &mut NoteColors) {
+    let mut key = note_colors.key;
+    ui.horizontal(|ui| {
+        ui.label("Color notes by");
+        for option in NoteColorKey::ALL {
+            ui.selectable_value(&mut key, option, option.name());
+        }
     });
+    if key != note_colors.key {
+        note_colors.key = key;
+    }
 }
 
-fn update_camera_transform_system(
+fn midi_ports(ui: &mut egui::Ui, output: &MidiOutput, midi_settings: &mut MidiSettings) {
+    if output.ports().is_empty() {
+        ui.label("No output ports");
+    }
+
+    for (name, port) in output.ports().iter() {
+        let connected = midi_settings.port.as_ref() == Some(name);
+        if ui.selectable_label(connected, name).clicked() && !connected {
+            output.connect(port.clone());
+            midi_settings.port = Some(name.clone());
+        }
+    }
+
+    if ui.button("Refresh").clicked() {
+        output.refresh_ports();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn playhead_settings(
+    ui: &mut egui::Ui,
+    commands: &mut Commands,
+    canvas: &Canvas,
+    sequencer_settings: &GlobalSequencerSettings,
+    active_layer: &ActiveLayer,
+    playhead_query: &mut Query<(Entity, &mut Transform, &mut Playhead)>,
+    copy_playhead: &mut EventWriter<CopyPlayhead>,
+) {
+    let beat_width = sequencer_settings.beat_width(canvas.width);
+
+    let mut playheads = playhead_query.iter_mut().collect::<Vec<_>>();
+    playheads.sort_by_key(|(entity, transform, _)| (layer_of(transform), *entity));
+
+    for (entity, transform, playhead) in playheads.iter_mut() {
+        let entity = *entity;
+        let mut layer = layer_of(transform);
+        let mut direction = playhead.direction;
+        let mut rate = playhead.rate;
+        let playhead_start = playhead.start / beat_width;
+        let mut start = playhead_start;
+        let (begin, end) = playhead.bounds(canvas);
+        let (playhead_begin, playhead_end) = (begin / beat_width, end / beat_width);
+        let (mut loop_begin, mut loop_end) = (playhead_begin, playhead_end);
+        let mut whole_canvas = false;
+        let mut color = playhead.color.as_rgba_f32();
+        let mut muted = playhead.muted;
+        let mut solo = playhead.solo;
+        let mut remove = false;
+
+        egui::CollapsingHeader::new(format!("Layer {} playhead", layer))
+            .id_source(("playhead", entity))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.toggle_value(&mut muted, "Mute");
+                    ui.toggle_value(&mut solo, "Solo");
+                    if ui.button("Copy").clicked() {
+                        copy_playhead.send(CopyPlayhead(entity));
+                    }
+                    remove = ui.button("Remove").clicked();
+                });
+
+                egui::Grid::new(("playhead_settings", entity)).show(ui, |ui| {
+                    ui.label("Layer");
+                    ui.add(egui::DragValue::new(&mut layer).speed(0.05));
+                    ui.end_row();
+
+                    ui.label("Direction");
+                    egui::ComboBox::from_id_source(("playhead_direction", entity))
+                        .selected_text(direction.name())
+                        .show_ui(ui, |ui| {
+                            for option in PlayheadDirection::ALL {
+                                ui.selectable_value(&mut direction, option, option.name());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Rate");
+                    ui.add(
+                        egui::DragValue::new(&mut rate)
+                            .speed(0.01)
+                            .clamp_range(0.0..=MAX_PLAYHEAD_RATE)
+                            .suffix("×"),
+                    )
+                    .on_hover_text("Loops played per loop at the tempo");
+                    ui.end_row();
+
+                    ui.label("Start");
+                    ui.add(
+                        egui::DragValue::new(&mut start)
+                            .speed(0.05)
+                            .clamp_range(0.0..=sequencer_settings.beats_per_loop() as f32)
+                            .suffix(" beats"),
+                    )
+                    .on_hover_text("Where Stop rewinds to, into the loop");
+                    ui.end_row();
+
+                    let min_loop_beats = MIN_LOOP_WIDTH / beat_width;
+                    ui.label("Loop");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut loop_begin)
+                                .speed(0.05)
+                                .clamp_range(0.0..=loop_end - min_loop_beats),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut loop_end)
+                                .speed(0.05)
+                                .clamp_range(
+                                    loop_begin + min_loop_beats
+                                        ..=sequencer_settings.beats_per_loop() as f32,
+                                )
+                                .suffix(" beats"),
+                        );
+                        whole_canvas = ui
+                            .add_enabled(playhead.region.is_some(), egui::Button::new("Whole"))
+                            .on_hover_text("Loop over the whole canvas")
+                            .clicked();
+                    });
+                    ui.end_row();
+
+                    ui.label("Color");
+                    ui.color_edit_button_rgba_unmultiplied(&mut color);
+                    ui.end_row();
+                });
+            });
+
+        if remove {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        if rate != playhead.rate {
+            playhead.rate = rate;
+        }
+        if direction != playhead.direction {
+            playhead.direction = direction;
+            playhead.current_direction = direction.initial();
+        }
+        if start != playhead_start {
+            playhead.start = start * beat_width;
+        }
+        if Color::from(color) != playhead.color {
+            playhead.color = Color::from(color);
+        }
+        if whole_canvas {
+            playhead.region = None;
+        } else if (loop_begin, loop_end) != (playhead_begin, playhead_end) {
+            playhead.region = Some(LoopRegion {
+                start: loop_begin * beat_width,
+                end: loop_end * beat_width,
+            });
+        }
+        if (muted, solo) != (playhead.muted, playhead.solo) {
+            playhead.muted = muted;
+            playhead.solo = solo;
+        }
+        if layer != layer_of(transform) {
+            transform.translation.z = layer as f32;
+        }
+    }
+
+    if ui.button("Add playhead").clicked() {
+        let mut playhead = Playhead::default();
+        let mut transform = Transform::from_xyz(0., 0., active_layer.0 as f32);
+        playhead.rewind(&mut transform, canvas);
+        spawn_playhead(commands, canvas, transform.translation, playhead);
+    }
+}
+
+fn layer_mixer(ui: &mut egui::Ui, layers: &mut Layers, layers_in_use: &BTreeSet<usize>) {
+    egui::Grid::new("layer_mixer").show(ui, |ui| {
+        for layer in layers_in_use.iter().copied() {
+            let settings = layers.get(layer);
+            let mut muted = settings.muted;
+            let mut solo = settings.solo;
+            let mut velocity = settings.velocity;
+
+            ui.label(format!("Layer {}", layer));
+            ui.toggle_value(&mut muted, "M").on_hover_text("Mute");
+            ui.toggle_value(&mut solo, "S").on_hover_text("Solo");
+            ui.add(
+                egui::DragValue::new(&mut velocity)
+                    .speed(0.01)
+                    .clamp_range(0.0..=MAX_LAYER_VELOCITY)
+                    .suffix("×"),
+            )
+            .on_hover_text("Velocity");
+            ui.end_row();
+
+            if (muted, solo, velocity) != (settings.muted, settings.solo, settings.velocity) {
+                let settings = layers.get_mut(layer);
+                settings.muted = muted;
+                settings.solo = solo;
+                settings.velocity = velocity;
+            }
+        }
+    });
+}
+
+/// Shrinks the 2D camera's viewport to the part of the window no panel covers.
+pub fn update_camera_viewport(
     occupied_screen_space: Res<OccupiedScreenSpace>,
-    original_camera_transform: Res<OriginalCameraTransform>,
-    windows: Query<&Window, With<PrimaryWindow>>,
-    mut camera_query: Query<(&Projection, &mut Transform)>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<&mut Camera, With<Camera2d>>,
 ) {
-    let (camera_projection, mut transform) = match camera_query.get_single_mut() {
-        Ok((Projection::Perspective(projection), transform)) => (projection, transform),
-        _ => unreachable!(),
+    let window = window_query.get_single().unwrap();
+    let scale_factor = window.scale_factor() as f32;
+    let window_size = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+
+    let position = Vec2::new(occupied_screen_space.left, occupied_screen_space.top) * scale_factor;
+    let size = window_size
+        - Vec2::new(
+            occupied_screen_space.left + occupied_screen_space.right,
+            occupied_screen_space.top + occupied_screen_space.bottom,
+        ) * scale_factor;
+    if size.x < 1. || size.y < 1. {
+        return;
+    }
+
+    let viewport = Viewport {
+        physical_position: position.as_uvec2(),
+        physical_size: size.as_uvec2(),
+        ..default()
     };
 
-    let distance_to_target = (CAMERA_TARGET - original_camera_transform.translation).length();
-    let frustum_height = 2.0 * distance_to_target * (camera_projection.fov * 0.5).tan();
-    let frustum_width = frustum_height * camera_projection.aspect_ratio;
-
-    let window = windows.single();
-
-    let left_taken = occupied_screen_space.left / window.width();
-    let right_taken = occupied_screen_space.right / window.width();
-    let top_taken = occupied_screen_space.top / window.height();
-    let bottom_taken = occupied_screen_space.bottom / window.height();
-    transform.translation = original_camera_transform.translation
-        + transform.rotation.mul_vec3(Vec3::new(
-            (right_taken - left_taken) * frustum_width * 0.5,
-            (top_taken - bottom_taken) * frustum_height * 0.5,
-            0.0,
-        ));
+    let mut camera = camera_query.single_mut();
+    let unchanged = camera.viewport.as_ref().is_some_and(|current| {
+        current.physical_position == viewport.physical_position
+            && current.physical_size == viewport.physical_size
+    });
+    if !unchanged {
+        camera.viewport = Some(viewport);
+    }
 }
-
-// fn ui_example_system(mut contexts: EguiContexts, output: Res<MidiOutput>) {
-//     egui::SidePanel::left("Hello").show(contexts.ctx_mut(), |ui| {
-//         ui.label("world");
-//         for (i, (name, _)) in output.ports().iter().enumerate() {
-//             ui.label(format!("Port {:?}: {:?}", i, name));
-//         }
-//     });
-// }
@@ -0,0 +1,319 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContexts};
+
+use super::{
+    keymap::{Action, Keymap},
+    layer::{layer_of, ActiveLayer},
+    note::{semitone_height, spawn_note},
+    playhead::{NoteOffEvent, Playhead},
+    sequence::{Canvas, GlobalSequencerSettings},
+};
+
+const MAX_STEPS: u32 = 64;
+/// Generated notes last this fraction of a step.
+const GATE: f32 = 0.5;
+const PULSE_SIZE: f32 = 12.0;
+
+pub struct EuclideanPlugin;
+
+impl Plugin for EuclideanPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EuclideanPatterns>()
+            .add_event::<GenerateEuclidean>()
+            .add_system(euclidean_window)
+            .add_system(generate_euclidean.after(euclidean_window));
+    }
+}
+
+/// `pulses` onsets spread as evenly as possible over `steps`, shifted left by `rotation`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EuclideanPattern {
+    pub steps: u32,
+    pub pulses: u32,
+    pub rotation: u32,
+    /// Pulses play these in turn.
+    pub pitches: Vec<u8>,
+}
+
+impl Default for EuclideanPattern {
+    fn default() -> Self {
+        EuclideanPattern {
+            steps: 16,
+            pulses: 5,
+            rotation: 0,
+            pitches: vec![60],
+        }
+    }
+}
+
+impl EuclideanPattern {
+    pub fn is_pulse(&self, step: u32) -> bool {
+        let step = (step + self.rotation) % self.steps;
+        (step * self.pulses) % self.steps < self.pulses
+    }
+
+    pub fn onsets(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.steps).filter(|step| self.is_pulse(*step))
+    }
+}
+
+/// The pattern last generated or edited on each layer.
+#[derive(Resource, Default, Debug)]
+pub struct EuclideanPatterns(pub HashMap<usize, EuclideanPattern>);
+
+/// Replaces the generated notes on a layer with its pattern.
+pub struct GenerateEuclidean(pub usize);
+
+/// A note placed by the Euclidean generator, so regenerating replaces only these.
+#[derive(Component)]
+pub struct EuclideanNote;
+
+/// Parses space or comma separated MIDI pitches, e.g. "60 63 67".
+fn parse_pitches(text: &str) -> Option<Vec<u8>> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<u8>().ok().filter(|pitch| *pitch <= 127))
+        .collect::<Option<Vec<u8>>>()
+        .filter(|pitches| !pitches.is_empty())
+}
+
+/// Spreads the steps over the loop of the layer's playhead, or the whole canvas on a
+/// layer without one.
+#[allow(clippy::too_many_arguments)]
+fn generate_euclidean(
+    mut commands: Commands,
+    mut generate_events: EventReader<GenerateEuclidean>,
+    patterns: Res<EuclideanPatterns>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    note_query: Query<(Entity, &Transform), With<EuclideanNote>>,
+    playhead_query: Query<(&Transform, &Playhead)>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+) {
+    for GenerateEuclidean(layer) in generate_events.iter() {
+        for (entity, transform) in note_query.iter() {
+            if layer_of(transform) == *layer {
+                midi_out_note_off.send(NoteOffEvent(entity));
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        let Some(pattern) = patterns.0.get(layer) else {
+            continue;
+        };
+        let (begin, end) = playhead_query
+            .iter()
+            .find(|(transform, _)| layer_of(transform) == *layer)
+            .map_or((0., canvas.width), |(_, playhead)| playhead.bounds(&canvas));
+        let step_width = (end - begin) / pattern.steps as f32;
+        let lane_height = semitone_height(canvas.height, &sequencer_settings);
+
+        for (pulse, step) in pattern.onsets().enumerate() {
+            let pitch = pattern.pitches[pulse % pattern.pitches.len()];
+            let lane = pitch as f32 - sequencer_settings.pitch_min as f32;
+            if lane < 0. || pitch >= sequencer_settings.pitch_max {
+                continue;
+            }
+
+            let width = step_width * GATE;
+            let translation = Vec3::new(
+                begin + step as f32 * step_width + width / 2.,
+                (lane + 0.5) * lane_height,
+                *layer as f32,
+            );
+            let note = spawn_note(&mut commands, translation, Vec2::new(width, lane_height));
+            commands.entity(note).insert(EuclideanNote);
+        }
+    }
+}
+
+/// Toggled with E by default. Edits the pattern of one layer at a time.
+#[allow(clippy::too_many_arguments)]
+fn euclidean_window(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    active_layer: Res<ActiveLayer>,
+    mut patterns: ResMut<EuclideanPatterns>,
+    mut generate_events: EventWriter<GenerateEuclidean>,
+    mut open: Local<bool>,
+    mut layer: Local<Option<usize>>,
+    mut pitches_text: Local<String>,
+) {
+    let ctx = contexts.ctx_mut();
+    if !ctx.wants_keyboard_input() && keymap.just_pressed(Action::ToggleEuclidean, &keyboard_input)
+    {
+        *open = !*open;
+    }
+    if !*open {
+        // Opening again starts on the active layer.
+        *layer = None;
+        return;
+    }
+
+    let mut window_open = *open;
+    egui::Window::new("Euclidean rhythm")
+        .open(&mut window_open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let mut edited_layer = layer.unwrap_or(active_layer.0);
+            let mut pattern = patterns.0.get(&edited_layer).cloned().unwrap_or_default();
+            let mut cleared = false;
+            if layer.is_none() {
+                *pitches_text = pitch_text(&pattern.pitches);
+            }
+
+            egui::Grid::new("euclidean_settings").show(ui, |ui| {
+                ui.label("Layer");
+                if ui.add(egui::DragValue::new(&mut edited_layer)).changed() {
+                    pattern = patterns.0.get(&edited_layer).cloned().unwrap_or_default();
+                    *pitches_text = pitch_text(&pattern.pitches);
+                }
+                ui.end_row();
+                ui.label("Steps");
+                ui.add(egui::DragValue::new(&mut pattern.steps).clamp_range(1..=MAX_STEPS));
+                ui.end_row();
+                ui.label("Pulses");
+                ui.add(egui::DragValue::new(&mut pattern.pulses).clamp_range(0..=pattern.steps));
+                ui.end_row();
+                ui.label("Rotation");
+                ui.add(
+                    egui::DragValue::new(&mut pattern.rotation).clamp_range(0..=pattern.steps - 1),
+                );
+                ui.end_row();
+                ui.label("Pitches");
+                ui.text_edit_singleline(&mut *pitches_text)
+                    .on_hover_text("MIDI pitches pulses play in turn, e.g. 60 63 67");
+                ui.end_row();
+            });
+            pattern.pulses = pattern.pulses.min(pattern.steps);
+            pattern.rotation = pattern.rotation.min(pattern.steps - 1);
+            if let Some(pitches) = parse_pitches(&pitches_text) {
+                pattern.pitches = pitches;
+            }
+
+            ui.horizontal_wrapped(|ui| {
+                for step in 0..pattern.steps {
+                    let (rect, _) =
+                        ui.allocate_exact_size(egui::Vec2::splat(PULSE_SIZE), egui::Sense::hover());
+                    let painter = ui.painter();
+                    if pattern.is_pulse(step) {
+                        painter.circle_filled(
+                            rect.center(),
+                            PULSE_SIZE / 2. - 1.,
+                            egui::Color32::GREEN,
+                        );
+                    } else {
+                        painter.circle_stroke(
+                            rect.center(),
+                            PULSE_SIZE / 2. - 1.,
+                            egui::Stroke::new(1., egui::Color32::GRAY),
+                        );
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Generate").clicked() {
+                    generate_events.send(GenerateEuclidean(edited_layer));
+                }
+                if ui.button("Generate all layers").clicked() {
+                    for layer in patterns.0.keys().filter(|layer| **layer != edited_layer) {
+                        generate_events.send(GenerateEuclidean(*layer));
+                    }
+                    generate_events.send(GenerateEuclidean(edited_layer));
+                }
+                if ui.button("Clear").clicked() {
+                    cleared = true;
+                    generate_events.send(GenerateEuclidean(edited_layer));
+                }
+            });
+
+            if cleared {
+                patterns.0.remove(&edited_layer);
+                *pitches_text = pitch_text(&EuclideanPattern::default().pitches);
+            } else if patterns.0.get(&edited_layer) != Some(&pattern) {
+                patterns.0.insert(edited_layer, pattern);
+            }
+            *layer = Some(edited_layer);
+        });
+    *open = window_open;
+}
+
+fn pitch_text(pitches: &[u8]) -> String {
+    pitches
+        .iter()
+        .map(|pitch| pitch.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(steps: u32, pulses: u32, rotation: u32) -> EuclideanPattern {
+        EuclideanPattern {
+            steps,
+            pulses,
+            rotation,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn spreads_pulses_evenly() {
+        let cases = [
+            (pattern(8, 3, 0), vec![0, 3, 6]),
+            (pattern(16, 5, 0), vec![0, 4, 7, 10, 13]),
+            (pattern(4, 1, 0), vec![0]),
+            (pattern(4, 0, 0), vec![]),
+        ];
+        for (pattern, onsets) in cases {
+            assert_eq!(
+                pattern.onsets().collect::<Vec<u32>>(),
+                onsets,
+                "{:?}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn rotation_shifts_pulses_left() {
+        let cases = [
+            (pattern(8, 3, 1), vec![2, 5, 7]),
+            (pattern(8, 3, 3), vec![0, 3, 5]),
+            (pattern(16, 5, 4), vec![0, 3, 6, 9, 12]),
+        ];
+        for (pattern, onsets) in cases {
+            assert_eq!(
+                pattern.onsets().collect::<Vec<u32>>(),
+                onsets,
+                "{:?}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn pulses_fill_every_step_at_most() {
+        for pulses in [8, 9, 20] {
+            let pattern = pattern(8, pulses, 2);
+            assert_eq!(pattern.onsets().count(), 8, "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    fn parses_pitches() {
+        assert_eq!(parse_pitches("60 63 67"), Some(vec![60, 63, 67]));
+        assert_eq!(parse_pitches("0,127"), Some(vec![0, 127]));
+    }
+
+    #[test]
+    fn rejects_bad_pitches() {
+        for text in ["", "  ,", "128", "60 x", "-1", "60.5"] {
+            assert_eq!(parse_pitches(text), None, "{:?}", text);
+        }
+    }
+}
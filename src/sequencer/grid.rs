@@ -0,0 +1,356 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContexts};
+
+use super::{
+    camera::viewport_origin,
+    layer::{ActiveLayer, Layers},
+    midi::AuditionEvent,
+    note::semitone_height,
+    scale::{is_black_key, note_name},
+    sequence::{Canvas, GlobalSequencerSettings},
+};
+
+const ROOT_ROW_COLOR: Color = Color::rgba(1., 1., 1., 0.12);
+const SCALE_ROW_COLOR: Color = Color::rgba(1., 1., 1., 0.05);
+const BLACK_KEY_ROW_COLOR: Color = Color::rgba(0., 0., 0., 0.25);
+const OCTAVE_LINE_COLOR: Color = Color::rgba(1., 1., 1., 0.2);
+const OCTAVE_LINE_WIDTH: f32 = 1.0;
+
+const BAR_LINE_COLOR: Color = Color::rgba(1., 1., 1., 0.3);
+const BEAT_LINE_COLOR: Color = Color::rgba(1., 1., 1., 0.12);
+const STEP_LINE_COLOR: Color = Color::rgba(1., 1., 1., 0.04);
+const TIME_LINE_WIDTH: f32 = 1.0;
+
+const KEYBOARD_WIDTH: f32 = 48.0;
+/// Keys smaller than this, in screen pixels, are only labeled on C.
+const MIN_LABELED_KEY_HEIGHT: f32 = 12.0;
+const AUDITION_VELOCITY: u8 = 100;
+
+pub struct GridPlugin;
+
+impl Plugin for GridPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SnapSettings>()
+            .init_resource::<Snap>()
+            .add_system(snap.in_base_set(CoreSet::PreUpdate))
+            .add_system(pitch_rows)
+            .add_system(time_lines)
+            .add_system(keyboard_strip);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SnapResolution {
+    Off,
+    Quarter,
+    Eighth,
+    #[default]
+    Sixteenth,
+    EighthTriplet,
+    SixteenthTriplet,
+}
+
+impl SnapResolution {
+    pub const ALL: [SnapResolution; 6] = [
+        SnapResolution::Off,
+        SnapResolution::Quarter,
+        SnapResolution::Eighth,
+        SnapResolution::Sixteenth,
+        SnapResolution::EighthTriplet,
+        SnapResolution::SixteenthTriplet,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SnapResolution::Off => "Off",
+            SnapResolution::Quarter => "1/4",
+            SnapResolution::Eighth => "1/8",
+            SnapResolution::Sixteenth => "1/16",
+            SnapResolution::EighthTriplet => "1/8T",
+            SnapResolution::SixteenthTriplet => "1/16T",
+        }
+    }
+
+    /// Grid steps per beat, a beat being a quarter note.
+    pub fn steps_per_beat(&self) -> Option<f32> {
+        match self {
+            SnapResolution::Off => None,
+            SnapResolution::Quarter => Some(1.),
+            SnapResolution::Eighth => Some(2.),
+            SnapResolution::Sixteenth => Some(4.),
+            SnapResolution::EighthTriplet => Some(3.),
+            SnapResolution::SixteenthTriplet => Some(6.),
+        }
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct SnapSettings {
+    pub resolution: SnapResolution,
+    /// Snap notes vertically to the middle of a pitch lane.
+    pub pitch: bool,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        SnapSettings {
+            resolution: SnapResolution::default(),
+            pitch: true,
+        }
+    }
+}
+
+/// Snapping as it applies this frame, in world units. Holding Alt bypasses it.
+#[derive(Resource, Default, Debug)]
+pub struct Snap {
+    pub time_step: Option<f32>,
+    pub lane_height: Option<f32>,
+}
+
+impl Snap {
+    pub fn x(&self, x: f32) -> f32 {
+        match self.time_step {
+            Some(step) => (x / step).round() * step,
+            None => x,
+        }
+    }
+
+    pub fn y(&self, y: f32) -> f32 {
+        match self.lane_height {
+            Some(height) => ((y / height).floor() + 0.5) * height,
+            None => y,
+        }
+    }
+}
+
+fn snap(
+    keyboard_input: Res<Input<KeyCode>>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    snap_settings: Res<SnapSettings>,
+    mut snap: ResMut<Snap>,
+) {
+    if keyboard_input.any_pressed([KeyCode::LAlt, KeyCode::RAlt]) {
+        *snap = Snap::default();
+        return;
+    }
+
+    let beat_width = sequencer_settings.beat_width(canvas.width);
+    snap.time_step = snap_settings
+        .resolution
+        .steps_per_beat()
+        .map(|steps| beat_width / steps);
+    snap.lane_height = snap_settings
+        .pitch
+        .then(|| semitone_height(canvas.height, &sequencer_settings));
+}
+
+/// A vertical bar, beat or snap step line.
+#[derive(Component)]
+struct TimeLine;
+
+fn time_lines(
+    mut commands: Commands,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    snap_settings: Res<SnapSettings>,
+    line_query: Query<Entity, With<TimeLine>>,
+) {
+    if !canvas.is_changed() && !sequencer_settings.is_changed() && !snap_settings.is_changed() {
+        return;
+    }
+
+    for entity in line_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let beat_width = sequencer_settings.beat_width(canvas.width);
+    let steps_per_beat = snap_settings.resolution.steps_per_beat().unwrap_or(1.) as u32;
+    let steps = sequencer_settings.beats_per_loop() * steps_per_beat;
+    let steps_per_bar = sequencer_settings.beats_per_bar * steps_per_beat;
+
+    for step in 0..=steps {
+        let color = if step % steps_per_bar == 0 {
+            BAR_LINE_COLOR
+        } else if step % steps_per_beat == 0 {
+            BEAT_LINE_COLOR
+        } else {
+            STEP_LINE_COLOR
+        };
+        let x = step as f32 * beat_width / steps_per_beat as f32;
+
+        commands
+            .spawn(SpriteBundle {
+                transform: Transform {
+                    translation: Vec3::new(x, canvas.height / 2., -0.3),
+                    scale: Vec3::new(TIME_LINE_WIDTH, canvas.height, 1.),
+                    ..default()
+                },
+                sprite: Sprite { color, ..default() },
+                ..default()
+            })
+            .insert(TimeLine);
+    }
+}
+
+/// Background shading of one pitch lane: black keys darker, in-key pitches of the
+/// active layer lighter and a line below every C.
+#[derive(Component)]
+struct PitchRow;
+
+fn spawn_row(commands: &mut Commands, canvas: &Canvas, y: f32, height: f32, z: f32, color: Color) {
+    commands
+        .spawn(SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(canvas.width / 2., y, z),
+                scale: Vec3::new(canvas.width, height, 1.),
+                ..default()
+            },
+            sprite: Sprite { color, ..default() },
+            ..default()
+        })
+        .insert(PitchRow);
+}
+
+fn pitch_rows(
+    mut commands: Commands,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    layers: Res<Layers>,
+    active_layer: Res<ActiveLayer>,
+    row_query: Query<Entity, With<PitchRow>>,
+) {
+    if !canvas.is_changed()
+        && !sequencer_settings.is_changed()
+        && !layers.is_changed()
+        && !active_layer.is_changed()
+    {
+        return;
+    }
+
+    for entity in row_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    let layer = layers.get(active_layer.0);
+    let (scale, root) = sequencer_settings.scale_for(&layer);
+    let row_height = semitone_height(canvas.height, &sequencer_settings);
+
+    for (row, pitch) in (sequencer_settings.pitch_min..sequencer_settings.pitch_max).enumerate() {
+        let bottom = row as f32 * row_height;
+        let center = bottom + row_height / 2.;
+
+        let scale_color = match scale.degree(pitch, root) {
+            Some(0) => Some(ROOT_ROW_COLOR),
+            Some(_) => Some(SCALE_ROW_COLOR),
+            None => None,
+        };
+        let rows = [
+            is_black_key(pitch).then_some((center, row_height, -0.6, BLACK_KEY_ROW_COLOR)),
+            (pitch % 12 == 0).then_some((bottom, OCTAVE_LINE_WIDTH, -0.4, OCTAVE_LINE_COLOR)),
+            scale_color.map(|color| (center, row_height, -0.5, color)),
+        ];
+
+        for (y, height, z, color) in rows.into_iter().flatten() {
+            spawn_row(&mut commands, &canvas, y, height, z, color);
+        }
+    }
+}
+
+/// A piano keyboard along the left edge of the window, lined up with the pitch lanes.
+/// Holding a key down auditions it.
+fn keyboard_strip(
+    mut contexts: EguiContexts,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    mut audition_events: EventWriter<AuditionEvent>,
+    mut held_key: Local<Option<u8>>,
+) {
+    let window = window_query.get_single().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+    let origin = viewport_origin(camera, window);
+    let row_height = semitone_height(canvas.height, &sequencer_settings);
+    // Bevy's viewport has y pointing up, egui's down.
+    let screen_y = |world_y: f32| {
+        camera
+            .world_to_viewport(camera_transform, Vec3::new(0., world_y, 0.))
+            .map(|position| window.height() - (position.y + origin.y))
+    };
+    let mut pressed_key = None;
+
+    egui::Area::new("keyboard_strip")
+        .fixed_pos(egui::pos2(origin.x, 0.))
+        .order(egui::Order::Background)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.allocate_exact_size(
+                egui::vec2(KEYBOARD_WIDTH, window.height()),
+                egui::Sense::hover(),
+            );
+            // Only presses starting on the keyboard play it, not drags from the canvas.
+            let pointer_down = ui.input(|input| {
+                input.pointer.primary_down()
+                    && input
+                        .pointer
+                        .press_origin()
+                        .is_some_and(|press| (0.0..KEYBOARD_WIDTH).contains(&(press.x - origin.x)))
+            });
+
+            for (row, pitch) in
+                (sequencer_settings.pitch_min..sequencer_settings.pitch_max).enumerate()
+            {
+                let (Some(top), Some(bottom)) = (
+                    screen_y((row + 1) as f32 * row_height),
+                    screen_y(row as f32 * row_height),
+                ) else {
+                    continue;
+                };
+                if bottom < 0. || top > window.height() {
+                    continue;
+                }
+
+                let rect = egui::Rect::from_min_max(
+                    egui::pos2(origin.x, top),
+                    egui::pos2(origin.x + KEYBOARD_WIDTH, bottom),
+                );
+                let pressed = pointer_down && ui.rect_contains_pointer(rect);
+                if pressed {
+                    pressed_key = Some(pitch);
+                }
+
+                let (fill, text_color) = if pressed {
+                    (egui::Color32::LIGHT_BLUE, egui::Color32::BLACK)
+                } else if is_black_key(pitch) {
+                    (egui::Color32::from_gray(30), egui::Color32::LIGHT_GRAY)
+                } else {
+                    (egui::Color32::from_gray(230), egui::Color32::DARK_GRAY)
+                };
+                ui.painter()
+                    .rect(rect, 0., fill, egui::Stroke::new(0.5, egui::Color32::GRAY));
+
+                if rect.height() >= MIN_LABELED_KEY_HEIGHT || pitch % 12 == 0 {
+                    ui.painter().text(
+                        rect.left_center() + egui::vec2(4., 0.),
+                        egui::Align2::LEFT_CENTER,
+                        note_name(pitch),
+                        egui::FontId::monospace(rect.height().clamp(6., 12.)),
+                        text_color,
+                    );
+                }
+            }
+        });
+
+    if pressed_key != *held_key {
+        if let Some(key) = *held_key {
+            audition_events.send(AuditionEvent { key, velocity: 0 });
+        }
+        if let Some(key) = pressed_key {
+            audition_events.send(AuditionEvent {
+                key,
+                velocity: AUDITION_VELOCITY,
+            });
+        }
+        *held_key = pressed_key;
+    }
+}
@@ -0,0 +1,135 @@
+use std::cmp::Ordering;
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use super::{
+    automaton::AutomatonCell,
+    keymap::{Action, Keymap},
+    mouse_input::Selected,
+    note::Note,
+    playhead::NoteOffEvent,
+    project::{Generator, GeneratorMarkers, ProjectNote},
+};
+
+/// Undo steps kept before the oldest are dropped.
+const MAX_UNDO_STEPS: usize = 100;
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>()
+            .add_system(undo_redo)
+            .add_system(record_history.in_base_set(CoreSet::PostUpdate));
+    }
+}
+
+/// Snapshots of the notes on the canvas, taken whenever an edit is finished. The
+/// automaton's notes aren't edits, it replaces them on its own. Other notes keep the
+/// generator that placed them, so it still replaces them after an undo.
+#[derive(Resource, Default, Debug)]
+pub struct History {
+    undo: Vec<Vec<ProjectNote>>,
+    redo: Vec<Vec<ProjectNote>>,
+    current: Option<Vec<ProjectNote>>,
+    /// Notes changed since the last snapshot.
+    dirty: bool,
+}
+
+/// Notes whose edits are recorded.
+type Recorded = (With<Note>, With<Sprite>, Without<AutomatonCell>);
+type Edited = Or<(Changed<Note>, Changed<Transform>)>;
+
+fn compare_notes(a: &ProjectNote, b: &ProjectNote) -> Ordering {
+    a.layer
+        .cmp(&b.layer)
+        .then(a.position.0.total_cmp(&b.position.0))
+        .then(a.position.1.total_cmp(&b.position.1))
+        .then(a.size.0.total_cmp(&b.size.0))
+        .then(a.size.1.total_cmp(&b.size.1))
+        .then(a.velocity.cmp(&b.velocity))
+        .then(a.channel.cmp(&b.channel))
+}
+
+/// Waits for drags to end, so a whole drag is one step.
+fn record_history(
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut history: ResMut<History>,
+    changed_query: Query<(), (Recorded, Edited)>,
+    mut removed_notes: RemovedComponents<Note>,
+    note_query: Query<(&Note, &Transform, GeneratorMarkers), Recorded>,
+) {
+    if !changed_query.is_empty() || removed_notes.iter().count() > 0 {
+        history.dirty = true;
+    }
+    if mouse_button_input.pressed(MouseButton::Left)
+        || (!history.dirty && history.current.is_some())
+    {
+        return;
+    }
+
+    let mut snapshot = note_query
+        .iter()
+        .map(|(note, transform, (generated, euclidean, cell))| {
+            let generator = Generator::of(euclidean, cell);
+            ProjectNote::new(note, transform, generated.is_some(), generator)
+        })
+        .collect::<Vec<ProjectNote>>();
+    snapshot.sort_by(compare_notes);
+    history.dirty = false;
+
+    if history.current.as_ref() == Some(&snapshot) {
+        return;
+    }
+    if let Some(previous) = history.current.replace(snapshot) {
+        history.undo.push(previous);
+        if history.undo.len() > MAX_UNDO_STEPS {
+            history.undo.remove(0);
+        }
+        history.redo.clear();
+    }
+}
+
+/// Ctrl+Z undoes, Ctrl+Shift+Z or Ctrl+Y redoes, by default.
+#[allow(clippy::too_many_arguments)]
+fn undo_redo(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut history: ResMut<History>,
+    mut selected: ResMut<Selected>,
+    note_query: Query<Entity, Recorded>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    let history = &mut *history;
+    let (from, to) = if keymap.just_pressed(Action::Undo, &keyboard_input) {
+        (&mut history.undo, &mut history.redo)
+    } else if keymap.just_pressed(Action::Redo, &keyboard_input) {
+        (&mut history.redo, &mut history.undo)
+    } else {
+        return;
+    };
+    if history.current.is_none() {
+        return;
+    }
+    let Some(snapshot) = from.pop() else {
+        return;
+    };
+    to.extend(history.current.take());
+
+    for entity in note_query.iter() {
+        midi_out_note_off.send(NoteOffEvent(entity));
+        commands.entity(entity).despawn_recursive();
+    }
+    for note in snapshot.iter() {
+        note.spawn(&mut commands);
+    }
+    selected.entities.clear();
+    history.current = Some(snapshot);
+}
@@ -0,0 +1,424 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use super::{
+    control_panel::{update_camera_viewport, OccupiedScreenSpace},
+    layer::layer_of,
+    mouse_input::Selected,
+    note::{semitone_height, Note, TrigCondition},
+    scale::note_name,
+    sequence::{Canvas, GlobalSequencerSettings},
+};
+
+/// Values closer than this count as the same across a selection.
+const COMMON_EPSILON: f32 = 1e-3;
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(note_inspector.before(update_camera_viewport));
+    }
+}
+
+/// How to change one value across the selection.
+#[derive(Clone, Copy, Debug)]
+enum Edit {
+    Set(f32),
+    Offset(f32),
+}
+
+impl Edit {
+    fn apply(self, value: f32, range: &RangeInclusive<f32>) -> f32 {
+        let value = match self {
+            Edit::Set(new) => new,
+            Edit::Offset(offset) => value + offset,
+        };
+        value.clamp(*range.start(), *range.end())
+    }
+}
+
+/// Offsets dragged so far for each value of a mixed selection.
+#[derive(Default)]
+struct InspectorOffsets {
+    selection: Vec<Entity>,
+    key: f32,
+    velocity: f32,
+    channel: f32,
+    layer: f32,
+    start: f32,
+    length: f32,
+    probability: f32,
+    hits: f32,
+    rate: f32,
+    velocity_step: f32,
+    pitch_step: f32,
+}
+
+/// A drag value for `values`, the selection's: absolute when they are all the same,
+/// otherwise an offset added to each. Whole-number values move in whole steps.
+#[allow(clippy::too_many_arguments)]
+fn common_value(
+    ui: &mut egui::Ui,
+    label: &str,
+    values: &[f32],
+    range: &RangeInclusive<f32>,
+    speed: f64,
+    whole: bool,
+    offset: &mut f32,
+    format: impl Fn(f64) -> String,
+) -> Option<Edit> {
+    ui.label(label);
+    let first = values[0];
+    let common = values
+        .iter()
+        .all(|value| (value - first).abs() < COMMON_EPSILON);
+
+    let round = |value: f32| if whole { value.round() } else { value };
+
+    let edit = if common {
+        let mut value = first;
+        let response = ui.add(
+            egui::DragValue::new(&mut value)
+                .speed(speed)
+                .clamp_range(range.clone())
+                .custom_formatter(|value, _| format(value)),
+        );
+        (response.changed() && round(value) != first).then_some(Edit::Set(round(value)))
+    } else {
+        let before = *offset;
+        ui.add(egui::DragValue::new(offset).speed(speed).prefix("± "))
+            .on_hover_text("Mixed values, drag to shift them all");
+        let delta = round(*offset) - round(before);
+        (delta != 0.).then_some(Edit::Offset(delta))
+    };
+    ui.end_row();
+
+    edit
+}
+
+/// The selection's condition, or "Mixed". Picking one sets it on every note, and
+/// conditions counting passes get their count beside them.
+fn condition_picker(ui: &mut egui::Ui, conditions: &[TrigCondition]) -> Option<TrigCondition> {
+    ui.label("Condition");
+    let first = conditions[0];
+    let common = conditions.iter().all(|condition| *condition == first);
+    let same_kind = |condition: &TrigCondition| {
+        std::mem::discriminant(condition) == std::mem::discriminant(&first)
+    };
+
+    let mut picked = None;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("note_condition")
+            .selected_text(if common { first.name() } else { "Mixed" })
+            .show_ui(ui, |ui| {
+                for option in TrigCondition::ALL {
+                    let current = common && same_kind(&option);
+                    if ui.selectable_label(current, option.name()).clicked() && !current {
+                        picked = Some(option);
+                    }
+                }
+            });
+
+        if common {
+            let mut condition = first;
+            if let TrigCondition::Every(n) | TrigCondition::Skip(n) = &mut condition {
+                ui.add(egui::DragValue::new(n).speed(0.05).clamp_range(2..=64));
+            }
+            if condition != first {
+                picked = Some(condition);
+            }
+        }
+    });
+    ui.end_row();
+
+    picked
+}
+
+/// Shows the selected notes in a panel on the right, every value editable.
+fn note_inspector(
+    mut contexts: EguiContexts,
+    selected: Res<Selected>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    mut occupied_screen_space: ResMut<OccupiedScreenSpace>,
+    mut note_query: Query<(&mut Note, &mut Transform)>,
+    mut offsets: Local<InspectorOffsets>,
+) {
+    let mut entities = selected
+        .entities
+        .iter()
+        .copied()
+        .filter(|entity| note_query.contains(*entity))
+        .collect::<Vec<Entity>>();
+    entities.sort();
+
+    if entities.is_empty() {
+        occupied_screen_space.right = 0.;
+        return;
+    }
+    if offsets.selection != entities {
+        *offsets = InspectorOffsets {
+            selection: entities.clone(),
+            ..default()
+        };
+    }
+
+    let beat_width = sequencer_settings.beat_width(canvas.width);
+    let lane_height = semitone_height(canvas.height, &sequencer_settings);
+    let pitch_min = sequencer_settings.pitch_min as f32;
+
+    let values = |value: &dyn Fn(&Note, &Transform) -> f32| {
+        entities
+            .iter()
+            .filter_map(|entity| note_query.get(*entity).ok())
+            .map(|(note, transform)| value(note, transform))
+            .collect::<Vec<f32>>()
+    };
+    let keys = values(&|note, _| note.key as f32);
+    let velocities = values(&|note, _| note.velocity as f32);
+    let channels = values(&|note, _| note.channel as f32);
+    let layers = values(&|_, transform| layer_of(transform) as f32);
+    let starts =
+        values(&|_, transform| (transform.translation.x - transform.scale.x / 2.) / beat_width);
+    let lengths = values(&|_, transform| transform.scale.x / beat_width);
+    let probabilities = values(&|note, _| (note.probability * 100.).round());
+    let hits = values(&|note, _| note.ratchet.hits as f32);
+    let rates = values(&|note, _| note.ratchet.rate);
+    let velocity_steps = values(&|note, _| note.ratchet.velocity_step as f32);
+    let pitch_steps = values(&|note, _| note.ratchet.pitch_step as f32);
+    let conditions = entities
+        .iter()
+        .filter_map(|entity| note_query.get(*entity).ok())
+        .map(|(note, _)| note.condition)
+        .collect::<Vec<TrigCondition>>();
+
+    let pitch_range = pitch_min..=sequencer_settings.pitch_max as f32 - 1.;
+    let velocity_range = 1.0..=127.0;
+    let channel_range = 0.0..=15.0;
+    let layer_range = 0.0..=f32::MAX;
+    let start_range = 0.0..=sequencer_settings.beats_per_loop() as f32;
+    let length_range = 1. / 64.0..=f32::MAX;
+    let probability_range = 0.0..=100.0;
+    let hits_range = 1.0..=16.0;
+    let rate_range = 1.0..=16.0;
+    let velocity_step_range = -64.0..=64.0;
+    let pitch_step_range = -24.0..=24.0;
+
+    let mut key_edit = None;
+    let mut velocity_edit = None;
+    let mut channel_edit = None;
+    let mut layer_edit = None;
+    let mut start_edit = None;
+    let mut length_edit = None;
+    let mut probability_edit = None;
+    let mut condition_edit = None;
+    let mut hits_edit = None;
+    let mut rate_edit = None;
+    let mut velocity_step_edit = None;
+    let mut pitch_step_edit = None;
+
+    occupied_screen_space.right = egui::SidePanel::right("note_inspector")
+        .resizable(true)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.heading(match entities.len() {
+                1 => "Note".to_string(),
+                count => format!("{} notes", count),
+            });
+
+            egui::Grid::new("note_inspector_values").show(ui, |ui| {
+                key_edit = common_value(
+                    ui,
+                    "Pitch",
+                    &keys,
+                    &pitch_range,
+                    0.5,
+                    true,
+                    &mut offsets.key,
+                    |key| format!("{} ({})", note_name(key.round() as u8), key.round()),
+                );
+                velocity_edit = common_value(
+                    ui,
+                    "Velocity",
+                    &velocities,
+                    &velocity_range,
+                    0.5,
+                    true,
+                    &mut offsets.velocity,
+                    |velocity| format!("{}", velocity.round()),
+                );
+                channel_edit = common_value(
+                    ui,
+                    "Channel",
+                    &channels,
+                    &channel_range,
+                    0.5,
+                    true,
+                    &mut offsets.channel,
+                    |channel| format!("{}", channel.round() + 1.),
+                );
+                layer_edit = common_value(
+                    ui,
+                    "Layer",
+                    &layers,
+                    &layer_range,
+                    0.5,
+                    true,
+                    &mut offsets.layer,
+                    |layer| format!("{}", layer.round()),
+                );
+                start_edit = common_value(
+                    ui,
+                    "Start",
+                    &starts,
+                    &start_range,
+                    0.05,
+                    false,
+                    &mut offsets.start,
+                    |beats| format!("{:.2} beats", beats),
+                );
+                length_edit = common_value(
+                    ui,
+                    "Length",
+                    &lengths,
+                    &length_range,
+                    0.05,
+                    false,
+                    &mut offsets.length,
+                    |beats| format!("{:.2} beats", beats),
+                );
+                probability_edit = common_value(
+                    ui,
+                    "Probability",
+                    &probabilities,
+                    &probability_range,
+                    0.5,
+                    true,
+                    &mut offsets.probability,
+                    |percent| format!("{}%", percent.round()),
+                );
+                condition_edit = condition_picker(ui, &conditions);
+            });
+
+            ui.separator();
+            ui.label("Ratchet");
+            egui::Grid::new("note_inspector_ratchet").show(ui, |ui| {
+                hits_edit = common_value(
+                    ui,
+                    "Hits",
+                    &hits,
+                    &hits_range,
+                    0.05,
+                    true,
+                    &mut offsets.hits,
+                    |hits| format!("{}", hits.round()),
+                );
+                rate_edit = common_value(
+                    ui,
+                    "Rate",
+                    &rates,
+                    &rate_range,
+                    0.05,
+                    true,
+                    &mut offsets.rate,
+                    |rate| format!("{} per beat", rate.round()),
+                );
+                velocity_step_edit = common_value(
+                    ui,
+                    "Velocity step",
+                    &velocity_steps,
+                    &velocity_step_range,
+                    0.5,
+                    true,
+                    &mut offsets.velocity_step,
+                    |step| format!("{:+}", step.round()),
+                );
+                pitch_step_edit = common_value(
+                    ui,
+                    "Pitch step",
+                    &pitch_steps,
+                    &pitch_step_range,
+                    0.1,
+                    true,
+                    &mut offsets.pitch_step,
+                    |step| format!("{:+}", step.round()),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                for semitones in [-12, -1, 1, 12] {
+                    if ui.button(format!("{:+}", semitones)).clicked() {
+                        key_edit = Some(Edit::Offset(semitones as f32));
+                    }
+                }
+            });
+            ui.allocate_rect(ui.available_rect_before_wrap(), egui::Sense::hover());
+        })
+        .response
+        .rect
+        .width();
+
+    for entity in entities {
+        let Ok((mut note, mut transform)) = note_query.get_mut(entity) else {
+            continue;
+        };
+
+        match key_edit {
+            // Into the middle of the key's lane.
+            Some(Edit::Set(key)) => {
+                transform.translation.y = (key.round() - pitch_min + 0.5) * lane_height;
+            }
+            // Keeping the note's offset within its lane.
+            Some(Edit::Offset(semitones)) => {
+                let key = note.key as f32;
+                let offset = Edit::Offset(semitones.round()).apply(key, &pitch_range) - key;
+                transform.translation.y += offset * lane_height;
+            }
+            None => {}
+        }
+        if let Some(edit) = velocity_edit {
+            note.velocity = edit.apply(note.velocity as f32, &velocity_range).round() as u8;
+        }
+        if let Some(edit) = channel_edit {
+            note.channel = edit.apply(note.channel as f32, &channel_range).round() as u8;
+        }
+        if let Some(edit) = probability_edit {
+            let percent = (note.probability * 100.).round();
+            note.probability = edit.apply(percent, &probability_range).round() / 100.;
+        }
+        if let Some(condition) = condition_edit {
+            note.condition = condition;
+        }
+        // Read by value, so only an actual edit marks the note changed.
+        let ratchet = note.ratchet;
+        if let Some(edit) = hits_edit {
+            note.ratchet.hits = edit.apply(ratchet.hits as f32, &hits_range).round() as u32;
+        }
+        if let Some(edit) = rate_edit {
+            note.ratchet.rate = edit.apply(ratchet.rate, &rate_range).round();
+        }
+        if let Some(edit) = velocity_step_edit {
+            let step = ratchet.velocity_step as f32;
+            note.ratchet.velocity_step = edit.apply(step, &velocity_step_range).round() as i8;
+        }
+        if let Some(edit) = pitch_step_edit {
+            let step = ratchet.pitch_step as f32;
+            note.ratchet.pitch_step = edit.apply(step, &pitch_step_range).round() as i8;
+        }
+        if let Some(edit) = layer_edit {
+            let layer = layer_of(&transform) as f32;
+            transform.translation.z = edit.apply(layer, &layer_range).round();
+        }
+
+        let start = (transform.translation.x - transform.scale.x / 2.) / beat_width;
+        let length = transform.scale.x / beat_width;
+        let start = start_edit.map_or(start, |edit| edit.apply(start, &start_range));
+        let length = length_edit.map_or(length, |edit| edit.apply(length, &length_range));
+        if start_edit.is_some() || length_edit.is_some() {
+            transform.scale.x = length * beat_width;
+            transform.translation.x = (start + length / 2.) * beat_width;
+        }
+    }
+}
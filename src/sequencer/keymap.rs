@@ -0,0 +1,420 @@
+use std::{fmt, fs, path::PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+pub struct KeymapPlugin;
+
+impl Plugin for KeymapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Keymap>()
+            .init_resource::<KeymapSettings>()
+            .add_startup_system(load_keymap)
+            .add_system(help_overlay);
+    }
+}
+
+/// Everything a key can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    PlayPause,
+    Stop,
+    /// Silences every note on every channel.
+    Panic,
+    Undo,
+    Redo,
+    Delete,
+    Duplicate,
+    Copy,
+    Cut,
+    Paste,
+    PasteAtPlayhead,
+    SelectAll,
+    NudgeLeft,
+    NudgeRight,
+    NudgeUp,
+    NudgeDown,
+    OctaveUp,
+    OctaveDown,
+    ZoomIn,
+    ZoomOut,
+    FollowPlayhead,
+    PreviousLayer,
+    NextLayer,
+    Layer(usize),
+    Save,
+    Open,
+    ToggleStepGrid,
+    ToggleAutomaton,
+    ToggleEuclidean,
+    Help,
+}
+
+impl Action {
+    /// Every action, in the order the help lists them.
+    pub fn all() -> impl Iterator<Item = Action> {
+        use Action::*;
+        [
+            PlayPause,
+            Stop,
+            Panic,
+            Undo,
+            Redo,
+            Delete,
+            Duplicate,
+            Copy,
+            Cut,
+            Paste,
+            PasteAtPlayhead,
+            SelectAll,
+            NudgeLeft,
+            NudgeRight,
+            NudgeUp,
+            NudgeDown,
+            OctaveUp,
+            OctaveDown,
+            ZoomIn,
+            ZoomOut,
+            FollowPlayhead,
+            PreviousLayer,
+            NextLayer,
+        ]
+        .into_iter()
+        .chain((0..=9).map(Layer))
+        .chain([
+            Save,
+            Open,
+            ToggleStepGrid,
+            ToggleAutomaton,
+            ToggleEuclidean,
+            Help,
+        ])
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Action::PlayPause => "Play or pause".to_string(),
+            Action::Stop => "Stop and rewind".to_string(),
+            Action::Panic => "Silence all notes".to_string(),
+            Action::Undo => "Undo".to_string(),
+            Action::Redo => "Redo".to_string(),
+            Action::Delete => "Delete selected notes".to_string(),
+            Action::Duplicate => "Duplicate selected notes".to_string(),
+            Action::Copy => "Copy selected notes".to_string(),
+            Action::Cut => "Cut selected notes".to_string(),
+            Action::Paste => "Paste at the mouse".to_string(),
+            Action::PasteAtPlayhead => "Paste at the playhead".to_string(),
+            Action::SelectAll => "Select all notes in the layer".to_string(),
+            Action::NudgeLeft => "Move selection earlier".to_string(),
+            Action::NudgeRight => "Move selection later".to_string(),
+            Action::NudgeUp => "Move selection a semitone up".to_string(),
+            Action::NudgeDown => "Move selection a semitone down".to_string(),
+            Action::OctaveUp => "Move selection an octave up".to_string(),
+            Action::OctaveDown => "Move selection an octave down".to_string(),
+            Action::ZoomIn => "Zoom in".to_string(),
+            Action::ZoomOut => "Zoom out".to_string(),
+            Action::FollowPlayhead => "Follow the playhead".to_string(),
+            Action::PreviousLayer => "Previous layer".to_string(),
+            Action::NextLayer => "Next layer".to_string(),
+            Action::Layer(layer) => format!("Layer {}", layer),
+            Action::Save => "Save project".to_string(),
+            Action::Open => "Open project".to_string(),
+            Action::ToggleStepGrid => "Show step grid".to_string(),
+            Action::ToggleAutomaton => "Show cellular automaton".to_string(),
+            Action::ToggleEuclidean => "Show Euclidean rhythm".to_string(),
+            Action::Help => "Show this help".to_string(),
+        }
+    }
+}
+
+/// A key along with the modifiers that must be held, and no others.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyChord {
+    pub key: KeyCode,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub alt: bool,
+}
+
+impl KeyChord {
+    const fn key(key: KeyCode) -> Self {
+        KeyChord {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    const fn ctrl(key: KeyCode) -> Self {
+        KeyChord {
+            ctrl: true,
+            ..KeyChord::key(key)
+        }
+    }
+
+    const fn shift(key: KeyCode) -> Self {
+        KeyChord {
+            shift: true,
+            ..KeyChord::key(key)
+        }
+    }
+
+    pub fn just_pressed(&self, keyboard_input: &Input<KeyCode>) -> bool {
+        keyboard_input.just_pressed(self.key)
+            && self.ctrl == keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl])
+            && self.shift == keyboard_input.any_pressed([KeyCode::LShift, KeyCode::RShift])
+            && self.alt == keyboard_input.any_pressed([KeyCode::LAlt, KeyCode::RAlt])
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+/// Which keys trigger which actions. An action can have several chords, or none.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct Keymap {
+    pub bindings: Vec<(Action, KeyChord)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use Action::*;
+        const LAYER_KEYS: [KeyCode; 10] = [
+            KeyCode::Key0,
+            KeyCode::Key1,
+            KeyCode::Key2,
+            KeyCode::Key3,
+            KeyCode::Key4,
+            KeyCode::Key5,
+            KeyCode::Key6,
+            KeyCode::Key7,
+            KeyCode::Key8,
+            KeyCode::Key9,
+        ];
+
+        let mut bindings = vec![
+            (PlayPause, KeyChord::key(KeyCode::Return)),
+            (Stop, KeyChord::key(KeyCode::Escape)),
+            (Panic, KeyChord::shift(KeyCode::Escape)),
+            (Undo, KeyChord::ctrl(KeyCode::Z)),
+            (
+                Redo,
+                KeyChord {
+                    shift: true,
+                    ..KeyChord::ctrl(KeyCode::Z)
+                },
+            ),
+            (Redo, KeyChord::ctrl(KeyCode::Y)),
+            (Delete, KeyChord::key(KeyCode::Delete)),
+            (Delete, KeyChord::key(KeyCode::Back)),
+            (Duplicate, KeyChord::ctrl(KeyCode::D)),
+            (Copy, KeyChord::ctrl(KeyCode::C)),
+            (Cut, KeyChord::ctrl(KeyCode::X)),
+            (Paste, KeyChord::ctrl(KeyCode::V)),
+            (
+                PasteAtPlayhead,
+                KeyChord {
+                    shift: true,
+                    ..KeyChord::ctrl(KeyCode::V)
+                },
+            ),
+            (SelectAll, KeyChord::ctrl(KeyCode::A)),
+            (NudgeLeft, KeyChord::key(KeyCode::Left)),
+            (NudgeRight, KeyChord::key(KeyCode::Right)),
+            (NudgeUp, KeyChord::key(KeyCode::Up)),
+            (NudgeDown, KeyChord::key(KeyCode::Down)),
+            (OctaveUp, KeyChord::shift(KeyCode::Up)),
+            (OctaveDown, KeyChord::shift(KeyCode::Down)),
+            (ZoomIn, KeyChord::key(KeyCode::Equals)),
+            (ZoomOut, KeyChord::key(KeyCode::Minus)),
+            (FollowPlayhead, KeyChord::key(KeyCode::F)),
+            (PreviousLayer, KeyChord::key(KeyCode::LBracket)),
+            (NextLayer, KeyChord::key(KeyCode::RBracket)),
+            (Save, KeyChord::ctrl(KeyCode::S)),
+            (Open, KeyChord::ctrl(KeyCode::O)),
+            (ToggleStepGrid, KeyChord::key(KeyCode::G)),
+            (ToggleAutomaton, KeyChord::key(KeyCode::L)),
+            (ToggleEuclidean, KeyChord::key(KeyCode::E)),
+            (Help, KeyChord::key(KeyCode::F1)),
+        ];
+        bindings.extend(
+            LAYER_KEYS
+                .into_iter()
+                .enumerate()
+                .map(|(layer, key)| (Layer(layer), KeyChord::key(key))),
+        );
+
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    pub fn chords(&self, action: Action) -> impl Iterator<Item = &KeyChord> {
+        self.bindings
+            .iter()
+            .filter(move |(bound, _)| *bound == action)
+            .map(|(_, chord)| chord)
+    }
+
+    pub fn just_pressed(&self, action: Action, keyboard_input: &Input<KeyCode>) -> bool {
+        self.chords(action)
+            .any(|chord| chord.just_pressed(keyboard_input))
+    }
+
+    /// The number key layer just pressed, if any.
+    pub fn layer_just_pressed(&self, keyboard_input: &Input<KeyCode>) -> Option<usize> {
+        self.bindings
+            .iter()
+            .find_map(|(action, chord)| match action {
+                Action::Layer(layer) if chord.just_pressed(keyboard_input) => Some(*layer),
+                _ => None,
+            })
+    }
+
+    /// Rebinds the actions `other` binds, keeping the bindings of the others.
+    fn merge(&mut self, other: Keymap) {
+        self.bindings
+            .retain(|(action, _)| !other.bindings.iter().any(|(rebound, _)| rebound == action));
+        self.bindings.extend(other.bindings);
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct KeymapSettings {
+    /// RON file whose bindings replace the defaults for the actions it lists.
+    pub path: PathBuf,
+}
+
+impl Default for KeymapSettings {
+    fn default() -> Self {
+        KeymapSettings {
+            path: PathBuf::from("keymap.ron"),
+        }
+    }
+}
+
+fn read_keymap(settings: &KeymapSettings) -> Result<Keymap, String> {
+    fs::read_to_string(&settings.path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| ron::from_str::<Keymap>(&text).map_err(|error| error.to_string()))
+}
+
+fn write_keymap(keymap: &Keymap, settings: &KeymapSettings) -> Result<(), String> {
+    ron::ser::to_string_pretty(keymap, ron::ser::PrettyConfig::default())
+        .map_err(|error| error.to_string())
+        .and_then(|text| fs::write(&settings.path, text).map_err(|error| error.to_string()))
+}
+
+/// Without a keymap file the defaults stay.
+fn load_keymap(mut keymap: ResMut<Keymap>, settings: Res<KeymapSettings>) {
+    if !settings.path.exists() {
+        return;
+    }
+
+    match read_keymap(&settings) {
+        Ok(loaded) => {
+            keymap.merge(loaded);
+            info!("loaded keymap {:?}", settings.path);
+        }
+        Err(error) => error!("could not load keymap {:?}: {}", settings.path, error),
+    }
+}
+
+/// Lists the current bindings. Toggled with F1 by default.
+fn help_overlay(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut keymap: ResMut<Keymap>,
+    settings: Res<KeymapSettings>,
+    mut open: Local<bool>,
+) {
+    let ctx = contexts.ctx_mut();
+    if !ctx.wants_keyboard_input() && keymap.just_pressed(Action::Help, &keyboard_input) {
+        *open = !*open;
+    }
+    if !*open {
+        return;
+    }
+
+    let mut reload = false;
+    let mut window_open = *open;
+    egui::Window::new("Keyboard shortcuts")
+        .open(&mut window_open)
+        .collapsible(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("keymap")
+                    .striped(true)
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        for action in Action::all() {
+                            let chords = keymap
+                                .chords(action)
+                                .map(|chord| chord.to_string())
+                                .collect::<Vec<String>>();
+                            ui.label(action.description());
+                            ui.monospace(if chords.is_empty() {
+                                "-".to_string()
+                            } else {
+                                chords.join(", ")
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+
+            ui.separator();
+            ui.label(format!(
+                "Bindings are read from {}",
+                settings.path.display()
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("Reload").clicked() {
+                    reload = true;
+                }
+                if ui
+                    .button("Write")
+                    .on_hover_text("Write the current bindings there to edit them")
+                    .clicked()
+                {
+                    match write_keymap(&keymap, &settings) {
+                        Ok(()) => info!("wrote keymap to {:?}", settings.path),
+                        Err(error) => {
+                            error!("could not write keymap to {:?}: {}", settings.path, error)
+                        }
+                    }
+                }
+            });
+        });
+    *open = window_open;
+
+    if reload {
+        match read_keymap(&settings) {
+            Ok(loaded) => {
+                let mut reloaded = Keymap::default();
+                reloaded.merge(loaded);
+                *keymap = reloaded;
+                info!("loaded keymap {:?}", settings.path);
+            }
+            Err(error) => error!("could not load keymap {:?}: {}", settings.path, error),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::EguiContexts;
+
+use super::{
+    keymap::{Action, Keymap},
+    scale::Scale,
+};
+
+pub struct LayerPlugin;
+
+impl Plugin for LayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveLayer>()
+            .init_resource::<Layers>()
+            .add_system(layer_shortcuts);
+    }
+}
+
+/// The layer new notes go to and layer-wide edits apply to.
+#[derive(Resource, Default, Debug)]
+pub struct ActiveLayer(pub usize);
+
+/// Per-layer settings, keyed by layer. Layers without an entry use the defaults.
+#[derive(Resource, Default, Debug)]
+pub struct Layers(pub HashMap<usize, Layer>);
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    /// Overrides the global scale for notes on this layer.
+    pub scale: Option<Scale>,
+    /// Overrides the global root for notes on this layer.
+    pub root: Option<u8>,
+    pub muted: bool,
+    /// While any layer is soloed, only soloed ones play.
+    pub solo: bool,
+    /// Scales the velocity of notes on this layer.
+    pub velocity: f32,
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Layer {
+            scale: None,
+            root: None,
+            muted: false,
+            solo: false,
+            velocity: 1.,
+        }
+    }
+}
+
+impl Layer {
+    /// `velocity` scaled by the layer, kept above 0 which would release the note.
+    pub fn scaled_velocity(&self, velocity: u8) -> u8 {
+        (velocity as f32 * self.velocity).round().clamp(1., 127.) as u8
+    }
+}
+
+impl Layers {
+    pub fn get(&self, layer: usize) -> Layer {
+        self.0.get(&layer).cloned().unwrap_or_default()
+    }
+
+    pub fn get_mut(&mut self, layer: usize) -> &mut Layer {
+        self.0.entry(layer).or_default()
+    }
+
+    /// Whether notes on `layer` play: it isn't muted, and it is soloed or no layer is.
+    pub fn audible(&self, layer: usize) -> bool {
+        let any_solo = self.0.values().any(|layer| layer.solo);
+        match self.0.get(&layer) {
+            Some(layer) => !layer.muted && (layer.solo || !any_solo),
+            None => !any_solo,
+        }
+    }
+}
+
+/// Opacity of notes and playheads that are silenced.
+pub const SILENT_ALPHA: f32 = 0.25;
+
+/// Notes and playheads share a layer when they share a z translation.
+pub fn layer_of(transform: &Transform) -> usize {
+    transform.translation.z as usize
+}
+
+/// Number keys pick a layer, brackets step through them, by default.
+fn layer_shortcuts(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut active_layer: ResMut<ActiveLayer>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    let layer = if let Some(layer) = keymap.layer_just_pressed(&keyboard_input) {
+        layer
+    } else if keymap.just_pressed(Action::PreviousLayer, &keyboard_input) {
+        active_layer.0.saturating_sub(1)
+    } else if keymap.just_pressed(Action::NextLayer, &keyboard_input) {
+        active_layer.0 + 1
+    } else {
+        return;
+    };
+
+    if layer != active_layer.0 {
+        active_layer.0 = layer;
+        info!("active layer: {}", layer);
+    }
+}
@@ -1,7 +1,24 @@
-use bevy::prelude::*;
+use std::ops::RangeInclusive;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::EguiContexts;
 use bevy_midi::prelude::{MidiOutput, MidiOutputPlugin};
 
-use super::{note::Note, playhead::{NoteOnEvent, NoteOffEvent}};
+use super::{
+    keymap::{Action, Keymap},
+    layer::{layer_of, Layers},
+    note::Note,
+    playhead::{check_for_collisions, NoteOffEvent, NoteOnEvent},
+    tuning::{LoadedTuning, TuningSettings},
+};
+
+/// MPE lower zone: channel 1 is the manager, 2-16 the members notes rotate through
+/// so each can carry its own pitch bend.
+const MEMBER_CHANNELS: RangeInclusive<u8> = 1..=15;
+const MANAGER_CHANNEL: u8 = 0;
+const PITCH_BEND_CENTER: i32 = 8192;
+const ALL_SOUND_OFF: u8 = 120;
+const ALL_NOTES_OFF: u8 = 123;
 
 pub struct MidiPlugin;
 
@@ -9,53 +26,252 @@ impl Plugin for MidiPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(MidiOutputPlugin)
             .init_resource::<MidiSettings>()
+            .init_resource::<SoundingNotes>()
+            .init_resource::<ChannelRotation>()
             .add_system(connect)
-            .add_system(midi_out_note_on)
-            .add_system(midi_out_note_off);
+            .add_system(configure_mpe.after(connect))
+            .add_event::<AuditionEvent>()
+            // A note retriggered in the same frame is released before it sounds again.
+            .add_system(midi_out_note_on.after(midi_out_note_off))
+            // Collisions sent this frame go out this frame, retriggers included.
+            .add_system(midi_out_note_off.after(check_for_collisions))
+            .add_system(midi_out_audition)
+            .add_system(midi_panic.after(midi_out_note_on));
     }
 }
 
+/// Plays a key directly, without a note on the canvas. Velocity 0 releases it.
+pub struct AuditionEvent {
+    pub key: u8,
+    pub velocity: u8,
+}
+
 #[derive(Resource, Default, Debug)]
-struct MidiSettings {
-    connected: bool,
+pub struct MidiSettings {
+    /// Name of the output port connected to.
+    pub port: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SoundingNote {
+    channel: u8,
+    pitch: u8,
+}
+
+/// What was sent with note on, so note off still matches after the note was moved or deleted.
+#[derive(Resource, Default, Debug)]
+struct SoundingNotes(HashMap<Entity, SoundingNote>);
+
+#[derive(Default, Debug)]
+struct Audition(Option<SoundingNote>);
+
+/// Member channel the next microtonal note starts looking from.
+#[derive(Resource, Default, Debug)]
+struct ChannelRotation(u8);
+
+fn control_change(channel: u8, controller: u8, value: u8) -> [u8; 3] {
+    [0b1011_0000 | channel, controller, value]
+}
+
+/// Sets registered parameter `parameter` on `channel` and deselects it again.
+fn registered_parameter(output: &MidiOutput, channel: u8, parameter: u8, coarse: u8, fine: u8) {
+    output.send(control_change(channel, 101, 0).into());
+    output.send(control_change(channel, 100, parameter).into());
+    output.send(control_change(channel, 6, coarse).into());
+    output.send(control_change(channel, 38, fine).into());
+    output.send(control_change(channel, 101, 127).into());
+    output.send(control_change(channel, 100, 127).into());
+}
+
+fn pitch_bend(channel: u8, bend: i16) -> [u8; 3] {
+    let value = (PITCH_BEND_CENTER + bend as i32).clamp(0, 16383) as u16;
+    [
+        0b1110_0000 | channel,
+        (value & 0x7f) as u8,
+        (value >> 7) as u8,
+    ]
 }
 
 fn connect(output: Res<MidiOutput>, mut midi_settings: ResMut<MidiSettings>) {
-    if midi_settings.connected {
+    if midi_settings.port.is_some() {
         return;
     }
 
-    if let Some((_, port)) = output.ports().get(0) {
+    if let Some((name, port)) = output.ports().first() {
         output.connect(port.clone());
-        midi_settings.connected = true;
-        println!("Connected");
+        midi_settings.port = Some(name.clone());
+        info!("connected to {}", name);
+    }
+}
+
+/// Announces the MPE zone and the pitch bend range whenever a tuning is loaded.
+fn configure_mpe(
+    midi_settings: Res<MidiSettings>,
+    tuning_settings: Res<TuningSettings>,
+    loaded_tuning: Res<LoadedTuning>,
+    output: Res<MidiOutput>,
+) {
+    if midi_settings.port.is_none()
+        || loaded_tuning.0.is_none()
+        || !(loaded_tuning.is_changed() || midi_settings.is_changed())
+    {
+        return;
+    }
+
+    let semitones = tuning_settings.pitch_bend_range.trunc();
+    let cents = (tuning_settings.pitch_bend_range.fract() * 100.).round();
+
+    registered_parameter(
+        &output,
+        MANAGER_CHANNEL,
+        6,
+        MEMBER_CHANNELS.count() as u8,
+        0,
+    );
+    for channel in MEMBER_CHANNELS {
+        registered_parameter(&output, channel, 0, semitones as u8, cents as u8);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn midi_out_note_on(
-    note_query: Query<&Note, With<Note>>,
+    note_query: Query<(&Note, Option<&Transform>)>,
+    layers: Res<Layers>,
     mut event_midi_out: EventReader<NoteOnEvent>,
+    mut sounding_notes: ResMut<SoundingNotes>,
+    mut channel_rotation: ResMut<ChannelRotation>,
+    loaded_tuning: Res<LoadedTuning>,
+    tuning_settings: Res<TuningSettings>,
     output: ResMut<MidiOutput>,
 ) {
     for ev in event_midi_out.iter() {
-        if let Ok(note) = note_query.get(ev.0) {
-            output.send([0b1001_0000, note.pitch, 127].into()); // Note on, channel 1
-            // println!("Midi note on: {}", note.pitch);
-            // output.send([0b1001_0000, note.pitch, 0].into()); // Note off, channel 1
-            // println!("Midi note off: {}", note.pitch);
+        if let Ok((note, transform)) = note_query.get(ev.0) {
+            // Later ratchet hits ramp the velocity and step the key, retuned like any
+            // other key.
+            let hit = ev.1;
+            let (pitch, bend) = if hit == 0 {
+                (note.pitch, note.bend)
+            } else {
+                let key = note.ratchet.pitch(note.key, hit);
+                loaded_tuning
+                    .0
+                    .as_ref()
+                    .and_then(|tuning| tuning.midi_note(key, tuning_settings.pitch_bend_range))
+                    .unwrap_or((key, 0))
+            };
+            let velocity = note.ratchet.velocity(note.velocity, hit);
+
+            let channel = if loaded_tuning.0.is_some() {
+                let channel = free_member_channel(&sounding_notes, channel_rotation.0);
+                channel_rotation.0 = channel;
+                output.send(pitch_bend(channel, bend).into());
+                channel
+            } else {
+                note.channel
+            };
+
+            // Notes off the canvas, like step grid voices, belong to no layer.
+            let velocity = match transform {
+                Some(transform) => layers.get(layer_of(transform)).scaled_velocity(velocity),
+                None => velocity,
+            };
+
+            output.send([0b1001_0000 | channel, pitch, velocity].into()); // Note on
+            sounding_notes
+                .0
+                .insert(ev.0, SoundingNote { channel, pitch });
         }
     }
 }
 
+/// The first member channel after `previous` with nothing sounding on it, or simply the
+/// next one when all are busy.
+fn free_member_channel(sounding_notes: &SoundingNotes, previous: u8) -> u8 {
+    let count = MEMBER_CHANNELS.count() as u8;
+    let first = *MEMBER_CHANNELS.start();
+    let after_previous = |offset: u8| first + (previous.saturating_sub(first) + offset) % count;
+
+    (1..=count)
+        .map(after_previous)
+        .find(|channel| {
+            !sounding_notes
+                .0
+                .values()
+                .any(|sounding| sounding.channel == *channel)
+        })
+        .unwrap_or_else(|| after_previous(1))
+}
+
 fn midi_out_note_off(
-    note_query: Query<&Note, With<Note>>,
     mut event_midi_out: EventReader<NoteOffEvent>,
+    mut sounding_notes: ResMut<SoundingNotes>,
     output: ResMut<MidiOutput>,
 ) {
     for ev in event_midi_out.iter() {
-        if let Ok(note) = note_query.get(ev.0) {
-            output.send([0b1001_0000, note.pitch, 0].into()); // Note off, channel 1
-            // println!("Midi note off: {}", note.pitch);
+        if let Some(SoundingNote { channel, pitch }) = sounding_notes.0.remove(&ev.0) {
+            output.send([0b1001_0000 | channel, pitch, 0].into()); // Note off
+        }
+    }
+}
+
+/// Shift+Escape by default. Releases everything on every channel, including notes
+/// this sequencer didn't start.
+fn midi_panic(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut sounding_notes: ResMut<SoundingNotes>,
+    output: Res<MidiOutput>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input()
+        || !keymap.just_pressed(Action::Panic, &keyboard_input)
+    {
+        return;
+    }
+
+    for SoundingNote { channel, pitch } in sounding_notes.0.drain().map(|(_, sounding)| sounding) {
+        output.send([0b1001_0000 | channel, pitch, 0].into());
+    }
+    for channel in 0..16 {
+        output.send(control_change(channel, ALL_NOTES_OFF, 0).into());
+        output.send(control_change(channel, ALL_SOUND_OFF, 0).into());
+    }
+    info!("panic: all notes off");
+}
+
+fn midi_out_audition(
+    mut audition_events: EventReader<AuditionEvent>,
+    mut audition: Local<Audition>,
+    sounding_notes: Res<SoundingNotes>,
+    mut channel_rotation: ResMut<ChannelRotation>,
+    tuning_settings: Res<TuningSettings>,
+    loaded_tuning: Res<LoadedTuning>,
+    output: ResMut<MidiOutput>,
+) {
+    for ev in audition_events.iter() {
+        if let Some(SoundingNote { channel, pitch }) = audition.0.take() {
+            output.send([0b1001_0000 | channel, pitch, 0].into()); // Note off
+        }
+
+        if ev.velocity == 0 {
+            continue;
         }
+
+        let tuned = loaded_tuning
+            .0
+            .as_ref()
+            .and_then(|tuning| tuning.midi_note(ev.key, tuning_settings.pitch_bend_range));
+        let (channel, pitch) = match tuned {
+            Some((pitch, bend)) => {
+                let channel = free_member_channel(&sounding_notes, channel_rotation.0);
+                channel_rotation.0 = channel;
+                output.send(pitch_bend(channel, bend).into());
+                (channel, pitch)
+            }
+            None => (0, ev.key),
+        };
+
+        output.send([0b1001_0000 | channel, pitch, ev.velocity].into()); // Note on
+        audition.0 = Some(SoundingNote { channel, pitch });
     }
 }
@@ -1,28 +1,87 @@
+mod automaton;
+mod camera;
+mod clipboard;
 mod control_panel;
+mod euclidean;
+mod grid;
+mod history;
+mod inspector;
+mod keymap;
+mod layer;
 mod midi;
 mod mouse_input;
 mod note;
 mod playhead;
+mod project;
+mod random;
+mod scale;
+mod scene;
 mod sequence;
+mod step_grid;
+mod tuning;
 
+use automaton::AutomatonPlugin;
+use camera::CameraPlugin;
+use clipboard::ClipboardPlugin;
 use control_panel::ControlPanelPlugin;
+use euclidean::EuclideanPlugin;
+use grid::GridPlugin;
+use history::HistoryPlugin;
+use inspector::InspectorPlugin;
+use keymap::KeymapPlugin;
+use layer::LayerPlugin;
 use midi::MidiPlugin;
 use mouse_input::MouseInputPlugin;
 use note::NotePlugin;
 use playhead::PlayheadPlugin;
+use project::ProjectPlugin;
+use random::{RandomPlugin, SequencerRng};
+use scene::ScenePlugin;
 use sequence::SequencePlugin;
+use step_grid::StepGridPlugin;
+use tuning::TuningPlugin;
 
 use bevy::prelude::*;
+use bevy_egui::EguiPlugin;
 
-pub struct SequencerPlugin;
+pub use scene::{RandomScene, StartupScene};
+
+#[derive(Default)]
+pub struct SequencerPlugin {
+    pub startup_scene: StartupScene,
+    pub random_scene: RandomScene,
+    /// Seed for everything random. A new one is picked when not given.
+    pub seed: Option<u64>,
+}
 
 impl Plugin for SequencerPlugin {
     fn build(&self, app: &mut App) {
-        // app.add_plugin(ControlPanelPlugin);
+        app.insert_resource(self.startup_scene.clone())
+            .insert_resource(self.random_scene.clone());
+        if let Some(seed) = self.seed {
+            app.insert_resource(SequencerRng::new(seed));
+        }
+
+        app.add_plugin(EguiPlugin);
+        app.add_plugin(KeymapPlugin);
+        app.add_plugin(ControlPanelPlugin);
+        app.add_plugin(InspectorPlugin);
         app.add_plugin(MidiPlugin);
+        app.add_plugin(TuningPlugin);
+        app.add_plugin(RandomPlugin);
+        app.add_plugin(LayerPlugin);
+        app.add_plugin(CameraPlugin);
         app.add_plugin(PlayheadPlugin);
         app.add_plugin(NotePlugin);
         app.add_plugin(SequencePlugin);
+        app.add_plugin(GridPlugin);
+        app.add_plugin(StepGridPlugin);
+        app.add_plugin(AutomatonPlugin);
+        app.add_plugin(EuclideanPlugin);
         app.add_plugin(MouseInputPlugin);
+        app.add_plugin(ClipboardPlugin);
+        app.add_plugin(ProjectPlugin);
+        app.add_plugin(HistoryPlugin);
+        app.add_plugin(ScenePlugin);
     }
 }
@@ -1,76 +1,986 @@
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy::{
+    prelude::*,
+    sprite::collide_aabb::collide,
+    utils::HashSet,
+    window::{CursorIcon, PrimaryWindow},
+};
+use bevy_egui::{egui, EguiContexts};
 
-use super::note::Note;
+use super::{
+    camera::{viewport_origin, Panning},
+    grid::Snap,
+    keymap::{Action, Keymap},
+    layer::{layer_of, ActiveLayer},
+    note::{semitone_height, spawn_note, Note},
+    playhead::{LoopEdge, NoteOffEvent, Playhead},
+    project::ProjectNote,
+    scene::RandomScene,
+    sequence::{Canvas, GlobalSequencerSettings, Transport},
+};
+
+const DOUBLE_CLICK_SECONDS: f64 = 0.3;
+const DOUBLE_CLICK_DISTANCE: f32 = 4.0;
+const RESIZE_HANDLE_WIDTH: f32 = 6.0;
+/// Shortest note resizing leaves when snapping is off.
+const MIN_NOTE_WIDTH: f32 = 4.0;
+const MIN_NOTE_BEATS: f32 = 1. / 16.;
+/// How close to a stopped playhead a click grabs it.
+const PLAYHEAD_GRAB_WIDTH: f32 = 8.0;
+/// Loop brackets are grabbed by their ends, this close to the top or bottom of the canvas.
+const LOOP_GRAB_HEIGHT: f32 = 40.0;
 
 pub struct MouseInputPlugin;
 
 impl Plugin for MouseInputPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Selected>()
+            .init_resource::<CursorPosition>()
+            .init_resource::<LastClick>()
+            .init_resource::<ContextMenu>()
+            .init_resource::<Resizing>()
+            .init_resource::<Dragging>()
+            .add_startup_system(spawn_resize_handles)
+            .add_startup_system(spawn_marquee)
+            .add_system(cursor_position.in_base_set(CoreSet::PreUpdate))
+            .add_system(start_resize.before(select_note))
+            .add_system(resize_note.after(start_resize))
+            .add_system(resize_handles.after(resize_note))
             .add_system(select_note)
-            .add_system(move_note);
+            .add_system(move_note.after(select_note))
+            .add_system(move_playhead.after(select_note))
+            .add_system(move_loop_edge.after(select_note))
+            .add_system(marquee_select.after(select_note))
+            .add_system(select_all_in_layer)
+            .add_system(create_note)
+            .add_system(delete_selected_notes)
+            .add_system(duplicate_selected_notes)
+            .add_system(nudge_selected_notes)
+            .add_system(open_context_menu.before(context_menu))
+            .add_system(context_menu);
     }
 }
 
-#[derive(Resource, Debug)]
+#[derive(Resource, Default, Debug)]
 pub struct Selected {
-    pub entity: Option<Entity>,
+    pub entities: HashSet<Entity>,
 }
 
-impl Default for Selected {
-    fn default() -> Self {
-        Selected { entity: None }
+impl Selected {
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    pub fn select_only(&mut self, entity: Entity) {
+        self.entities.clear();
+        self.entities.insert(entity);
+    }
+
+    pub fn toggle(&mut self, entity: Entity) {
+        if !self.entities.remove(&entity) {
+            self.entities.insert(entity);
+        }
     }
+
+    /// The selected note, if exactly one is selected.
+    pub fn single(&self) -> Option<Entity> {
+        match self.entities.len() {
+            1 => self.entities.iter().next().copied(),
+            _ => None,
+        }
+    }
+}
+
+/// The cursor in window coordinates and, through the camera, in world coordinates.
+#[derive(Resource, Default, Debug)]
+pub struct CursorPosition {
+    pub window: Option<Vec2>,
+    pub world: Option<Vec2>,
+}
+
+#[derive(Resource, Default, Debug)]
+struct LastClick {
+    time: f64,
+    position: Vec2,
+}
+
+/// Right-click menu, open while `position` (window coordinates) is set.
+#[derive(Resource, Default, Debug)]
+struct ContextMenu {
+    position: Option<Vec2>,
+    world_position: Vec2,
+    target: Option<Entity>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NoteEdge {
+    Left,
+    Right,
+}
+
+/// The note edge being dragged, if any.
+#[derive(Resource, Default, Debug)]
+struct Resizing {
+    note: Option<(Entity, NoteEdge)>,
+}
+
+#[derive(Component)]
+struct ResizeHandle(NoteEdge);
+
+/// What a left-button drag that didn't start on a note edge is doing.
+#[derive(Resource, Default, Debug)]
+enum Dragging {
+    #[default]
+    None,
+    /// Moves the selection along with the grabbed `anchor` point, the left edge and
+    /// middle of the note under the cursor, which is what snaps to the grid.
+    Notes {
+        start_cursor: Vec2,
+        anchor: Vec2,
+        offset: Vec2,
+    },
+    Marquee {
+        start: Vec2,
+        additive: bool,
+    },
+    /// Moves a playhead, only while the transport is stopped.
+    Playhead {
+        entity: Entity,
+        offset: f32,
+    },
+    LoopEdge {
+        playhead: Entity,
+        edge: LoopEdge,
+    },
+}
+
+#[derive(Component)]
+struct Marquee;
+
+fn shift_pressed(keyboard_input: &Input<KeyCode>) -> bool {
+    keyboard_input.any_pressed([KeyCode::LShift, KeyCode::RShift])
+}
+
+fn pointer_over_ui(contexts: &mut EguiContexts) -> bool {
+    let ctx = contexts.ctx_mut();
+    ctx.is_pointer_over_area() || ctx.is_using_pointer()
+}
+
+/// Whether the pointer belongs to the UI or the camera rather than the notes.
+fn pointer_captured(contexts: &mut EguiContexts, panning: &Panning) -> bool {
+    panning.0 || pointer_over_ui(contexts)
 }
 
-// This system prints messages when you press or release the left mouse button:
+fn note_at(
+    position: Vec2,
+    notes_query: &Query<(Entity, &Transform), With<Note>>,
+) -> Option<Entity> {
+    notes_query
+        .iter()
+        .find(|(_, transform)| {
+            collide(
+                transform.translation,
+                transform.scale.truncate(),
+                position.extend(transform.translation.z),
+                Vec2::new(1.0, 1.0),
+            )
+            .is_some()
+        })
+        .map(|(entity, _)| entity)
+}
+
+fn playhead_at(
+    position: Vec2,
+    playhead_query: &Query<(Entity, &Transform, &Playhead)>,
+) -> Option<(Entity, f32)> {
+    playhead_query
+        .iter()
+        .map(|(entity, transform, _)| (entity, transform.translation.x - position.x))
+        .filter(|(_, offset)| offset.abs() <= PLAYHEAD_GRAB_WIDTH / 2.)
+        .min_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+}
+
+/// Only set regions can be dragged, so the edges of the canvas stay free for notes. A
+/// playhead looping over the whole canvas gets a region from the control panel.
+fn loop_edge_at(
+    position: Vec2,
+    canvas: &Canvas,
+    playhead_query: &Query<(Entity, &Transform, &Playhead)>,
+) -> Option<(Entity, LoopEdge)> {
+    if position.y > LOOP_GRAB_HEIGHT && position.y < canvas.height - LOOP_GRAB_HEIGHT {
+        return None;
+    }
+
+    playhead_query
+        .iter()
+        .filter(|(_, _, playhead)| playhead.region.is_some())
+        .flat_map(|(entity, _, playhead)| {
+            let (begin, end) = playhead.bounds(canvas);
+            [
+                (entity, LoopEdge::Start, begin),
+                (entity, LoopEdge::End, end),
+            ]
+        })
+        .map(|(entity, edge, x)| (entity, edge, (x - position.x).abs()))
+        .filter(|(_, _, distance)| *distance <= PLAYHEAD_GRAB_WIDTH / 2.)
+        .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+        .map(|(entity, edge, _)| (entity, edge))
+}
+
+fn edge_at(
+    position: Vec2,
+    notes_query: &Query<(Entity, &Transform), With<Note>>,
+) -> Option<(Entity, NoteEdge)> {
+    for (entity, transform) in notes_query.iter() {
+        let half_size = transform.scale.truncate() / 2.;
+        let local = position - transform.translation.truncate();
+
+        if local.y.abs() > half_size.y {
+            continue;
+        }
+        if (local.x + half_size.x).abs() <= RESIZE_HANDLE_WIDTH / 2. {
+            return Some((entity, NoteEdge::Left));
+        }
+        if (local.x - half_size.x).abs() <= RESIZE_HANDLE_WIDTH / 2. {
+            return Some((entity, NoteEdge::Right));
+        }
+    }
+
+    None
+}
+
+/// Note length in beats, given its width on a loop of `loop_width`.
+fn note_beats(width: f32, loop_width: f32, settings: &GlobalSequencerSettings) -> f32 {
+    width / settings.beat_width(loop_width)
+}
+
+/// Where a new note goes when placed at `position`: centred on it, unless snapping
+/// moves its left edge onto the grid and its middle onto a pitch lane.
+fn new_note_translation(position: Vec2, size: Vec2, snap: &Snap, layer: usize) -> Vec3 {
+    let left = snap.x(position.x - size.x / 2.);
+    Vec3::new(left + size.x / 2., snap.y(position.y), layer as f32)
+}
+
+pub fn delete_note(
+    commands: &mut Commands,
+    midi_out_note_off: &mut EventWriter<NoteOffEvent>,
+    entity: Entity,
+) {
+    if let Some(entity_commands) = commands.get_entity(entity) {
+        info!("deleted note: {:?}", entity);
+        midi_out_note_off.send(NoteOffEvent(entity));
+        entity_commands.despawn_recursive();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn select_note(
-    mut cursor_moved_events: EventReader<CursorMoved>,
+    mut contexts: EguiContexts,
+    panning: Res<Panning>,
+    cursor: Res<CursorPosition>,
     mouse_button_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    resizing: Res<Resizing>,
+    mut dragging: ResMut<Dragging>,
     mut selected: ResMut<Selected>,
+    mut active_layer: ResMut<ActiveLayer>,
     notes_query: Query<(Entity, &Transform), With<Note>>,
+    (canvas, transport): (Res<Canvas>, Res<Transport>),
+    playhead_query: Query<(Entity, &Transform, &Playhead)>,
 ) {
-    let mut cursor_position: Vec3 = Vec3::new(0., 0., 0.);
+    if !mouse_button_input.just_pressed(MouseButton::Left)
+        || pointer_captured(&mut contexts, &panning)
+        || resizing.note.is_some()
+    {
+        return;
+    }
 
-    for event in cursor_moved_events.iter() {
-        cursor_position = Vec3::new(event.position.x, event.position.y, 0.0);
+    let Some(cursor_position) = cursor.world else {
+        return;
+    };
+
+    if let Some((playhead, edge)) = loop_edge_at(cursor_position, &canvas, &playhead_query) {
+        info!("moving loop edge: {:?}", playhead);
+        *dragging = Dragging::LoopEdge { playhead, edge };
+        return;
+    }
+    if !transport.playing {
+        if let Some((entity, offset)) = playhead_at(cursor_position, &playhead_query) {
+            info!("moving playhead: {:?}", entity);
+            *dragging = Dragging::Playhead { entity, offset };
+            return;
+        }
     }
+    let additive = shift_pressed(&keyboard_input);
 
-    if mouse_button_input.just_pressed(MouseButton::Left) {
-        for (entity, transform) in notes_query.iter() {
-            let collision = collide(
-                transform.translation,
-                transform.scale.truncate(),
-                cursor_position,
-                Vec2::new(1.0, 1.0),
-            );
+    match note_at(cursor_position, &notes_query) {
+        Some(entity) => {
+            if additive {
+                info!("toggled note: {:?}", entity);
+                selected.toggle(entity);
+                return;
+            }
 
-            if collision.is_some() {
-                if mouse_button_input.pressed(MouseButton::Left) {
-                    info!("selected note: {:?}", entity);
-                    selected.entity = Some(entity);
-                } else if mouse_button_input.just_released(MouseButton::Left) {
-                    info!("deselected note: {:?}", entity);
-                    selected.entity = None;
-                }
+            if !selected.contains(entity) {
+                info!("selected note: {:?}", entity);
+                selected.select_only(entity);
+            }
+            if let Ok((_, transform)) = notes_query.get(entity) {
+                active_layer.0 = layer_of(transform);
+                *dragging = Dragging::Notes {
+                    start_cursor: cursor_position,
+                    anchor: transform.translation.truncate()
+                        - Vec2::new(transform.scale.x / 2., 0.),
+                    offset: Vec2::ZERO,
+                };
             }
         }
+        None => {
+            if !additive {
+                selected.entities.clear();
+            }
+            *dragging = Dragging::Marquee {
+                start: cursor_position,
+                additive,
+            };
+        }
     }
 }
 
 fn move_note(
-    mut cursor_moved_events: EventReader<CursorMoved>,
+    cursor: Res<CursorPosition>,
+    snap: Res<Snap>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    selected: Res<Selected>,
+    mut dragging: ResMut<Dragging>,
+    mut note_query: Query<&mut Transform, With<Note>>,
+) {
+    let Dragging::Notes {
+        start_cursor,
+        anchor,
+        offset,
+    } = *dragging
+    else {
+        return;
+    };
+
+    if !mouse_button_input.pressed(MouseButton::Left) {
+        *dragging = Dragging::None;
+        return;
+    }
+
+    let Some(cursor_position) = cursor.world else {
+        return;
+    };
+    let target = anchor + cursor_position - start_cursor;
+    let new_offset = Vec2::new(snap.x(target.x), snap.y(target.y)) - anchor;
+    let delta = new_offset - offset;
+
+    if delta != Vec2::ZERO {
+        for entity in selected.entities.iter() {
+            if let Ok(mut transform) = note_query.get_mut(*entity) {
+                transform.translation += delta.extend(0.);
+            }
+        }
+        *dragging = Dragging::Notes {
+            start_cursor,
+            anchor,
+            offset: new_offset,
+        };
+    }
+}
+
+fn move_playhead(
+    cursor: Res<CursorPosition>,
+    snap: Res<Snap>,
+    canvas: Res<Canvas>,
+    transport: Res<Transport>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut dragging: ResMut<Dragging>,
+    mut playhead_query: Query<&mut Transform, With<Playhead>>,
+) {
+    let Dragging::Playhead { entity, offset } = *dragging else {
+        return;
+    };
+
+    if !mouse_button_input.pressed(MouseButton::Left) || transport.playing {
+        *dragging = Dragging::None;
+        return;
+    }
+
+    let (Some(cursor_position), Ok(mut transform)) = (cursor.world, playhead_query.get_mut(entity))
+    else {
+        return;
+    };
+    let x = snap.x(cursor_position.x + offset).clamp(0., canvas.width);
+    if x != transform.translation.x {
+        transform.translation.x = x;
+    }
+}
+
+fn move_loop_edge(
+    cursor: Res<CursorPosition>,
+    snap: Res<Snap>,
+    canvas: Res<Canvas>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut dragging: ResMut<Dragging>,
+    mut playhead_query: Query<&mut Playhead>,
+) {
+    let Dragging::LoopEdge { playhead, edge } = *dragging else {
+        return;
+    };
+
+    if !mouse_button_input.pressed(MouseButton::Left) {
+        *dragging = Dragging::None;
+        return;
+    }
+
+    let (Some(cursor_position), Ok(mut playhead)) =
+        (cursor.world, playhead_query.get_mut(playhead))
+    else {
+        return;
+    };
+    let region = playhead.moved_loop_edge(edge, snap.x(cursor_position.x), &canvas);
+    if playhead.region != Some(region) {
+        playhead.region = Some(region);
+    }
+}
+
+fn marquee_select(
+    cursor: Res<CursorPosition>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut dragging: ResMut<Dragging>,
+    mut selected: ResMut<Selected>,
+    notes_query: Query<(Entity, &Transform), With<Note>>,
+    mut marquee_query: Query<&mut Transform, (With<Marquee>, Without<Note>)>,
+) {
+    let mut marquee_transform = marquee_query.single_mut();
+
+    let Dragging::Marquee { start, additive } = *dragging else {
+        marquee_transform.scale = Vec3::ZERO;
+        return;
+    };
+
+    let end = cursor.world.unwrap_or(start);
+    let min = start.min(end);
+    let max = start.max(end);
+
+    if mouse_button_input.pressed(MouseButton::Left) {
+        marquee_transform.translation = ((min + max) / 2.).extend(marquee_transform.translation.z);
+        marquee_transform.scale = (max - min).extend(1.);
+        return;
+    }
+
+    if !additive {
+        selected.entities.clear();
+    }
+    for (entity, transform) in notes_query.iter() {
+        let half_size = transform.scale.truncate() / 2.;
+        let note_min = transform.translation.truncate() - half_size;
+        let note_max = transform.translation.truncate() + half_size;
+
+        if note_min.cmple(max).all() && note_max.cmpge(min).all() {
+            selected.entities.insert(entity);
+        }
+    }
+
+    *dragging = Dragging::None;
+}
+
+fn select_all_in_layer(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    active_layer: Res<ActiveLayer>,
+    mut selected: ResMut<Selected>,
+    notes_query: Query<(Entity, &Transform), With<Note>>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input()
+        || !keymap.just_pressed(Action::SelectAll, &keyboard_input)
+    {
+        return;
+    }
+
+    selected.entities = notes_query
+        .iter()
+        .filter(|(_, transform)| layer_of(transform) == active_layer.0)
+        .map(|(entity, _)| entity)
+        .collect();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_note(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    panning: Res<Panning>,
+    cursor: Res<CursorPosition>,
+    snap: Res<Snap>,
+    random_scene: Res<RandomScene>,
     mouse_button_input: Res<Input<MouseButton>>,
+    time: Res<Time>,
+    mut last_click: ResMut<LastClick>,
+    mut selected: ResMut<Selected>,
+    active_layer: Res<ActiveLayer>,
+    notes_query: Query<(Entity, &Transform), With<Note>>,
+) {
+    if !mouse_button_input.just_pressed(MouseButton::Left)
+        || pointer_captured(&mut contexts, &panning)
+    {
+        return;
+    }
+
+    let (Some(position), Some(world_position)) = (cursor.window, cursor.world) else {
+        return;
+    };
+
+    let now = time.elapsed_seconds_f64();
+    let double_click = now - last_click.time < DOUBLE_CLICK_SECONDS
+        && last_click.position.distance(position) < DOUBLE_CLICK_DISTANCE;
+
+    if double_click {
+        // Forget the click so a third click starts a new double-click.
+        *last_click = LastClick::default();
+
+        if note_at(world_position, &notes_query).is_none() {
+            let entity = spawn_note(
+                &mut commands,
+                new_note_translation(
+                    world_position,
+                    random_scene.note_size,
+                    &snap,
+                    active_layer.0,
+                ),
+                random_scene.note_size,
+            );
+            info!("created note: {:?}", entity);
+            selected.select_only(entity);
+        }
+    } else {
+        *last_click = LastClick {
+            time: now,
+            position,
+        };
+    }
+}
+
+fn delete_selected_notes(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut selected: ResMut<Selected>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input()
+        || !keymap.just_pressed(Action::Delete, &keyboard_input)
+    {
+        return;
+    }
+
+    for entity in selected.entities.drain() {
+        delete_note(&mut commands, &mut midi_out_note_off, entity);
+    }
+}
+
+/// Width of the loop the active layer's playhead plays, the whole canvas without one.
+fn active_loop_width(
+    active_layer: &ActiveLayer,
+    playhead_query: &Query<(&Transform, &Playhead), Without<Note>>,
+    canvas: &Canvas,
+) -> f32 {
+    playhead_query
+        .iter()
+        .find(|(transform, _)| layer_of(transform) == active_layer.0)
+        .map_or(canvas.width, |(_, playhead)| {
+            let (begin, end) = playhead.bounds(canvas);
+            end - begin
+        })
+}
+
+/// Copies the selected notes one loop of the active layer's playhead later, so they play
+/// on the next pass, and selects the copies. Copies that would start off the canvas are
+/// left out, and nothing changes if none fit.
+fn duplicate_selection(
+    commands: &mut Commands,
+    selected: &mut Selected,
+    note_query: &Query<(&Note, &mut Transform)>,
+    loop_width: f32,
+    canvas: &Canvas,
+) {
+    let duplicates = selected
+        .entities
+        .iter()
+        .filter_map(|entity| note_query.get(*entity).ok())
+        .map(|(note, transform)| {
+            let mut duplicate = ProjectNote::new(note, transform, false, None);
+            duplicate.position.0 += loop_width;
+            duplicate
+        })
+        .filter(|duplicate| duplicate.on_canvas(canvas))
+        .map(|duplicate| duplicate.spawn(commands))
+        .collect::<HashSet<Entity>>();
+
+    if !duplicates.is_empty() {
+        selected.entities = duplicates;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn duplicate_selected_notes(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    canvas: Res<Canvas>,
+    active_layer: Res<ActiveLayer>,
+    mut selected: ResMut<Selected>,
+    note_query: Query<(&Note, &mut Transform)>,
+    playhead_query: Query<(&Transform, &Playhead), Without<Note>>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input()
+        || !keymap.just_pressed(Action::Duplicate, &keyboard_input)
+    {
+        return;
+    }
+
+    let loop_width = active_loop_width(&active_layer, &playhead_query, &canvas);
+    duplicate_selection(
+        &mut commands,
+        &mut selected,
+        &note_query,
+        loop_width,
+        &canvas,
+    );
+}
+
+/// Arrow keys move the selection by a grid step, or a sixteenth with snapping off, and
+/// by a semitone or an octave.
+#[allow(clippy::too_many_arguments)]
+fn nudge_selected_notes(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    snap: Res<Snap>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
     selected: Res<Selected>,
     mut note_query: Query<&mut Transform, With<Note>>,
 ) {
-    if selected.entity.is_some() && mouse_button_input.pressed(MouseButton::Left) {
-        if let Ok(mut transform) = note_query.get_mut(selected.entity.unwrap()) {
-            info!("moving note");
-            for event in cursor_moved_events.iter() {
-                transform.translation.x = event.position.x;
-                transform.translation.y = event.position.y;
+    if contexts.ctx_mut().wants_keyboard_input() || selected.entities.is_empty() {
+        return;
+    }
+
+    let time_step = snap
+        .time_step
+        .unwrap_or(sequencer_settings.beat_width(canvas.width) / 4.);
+    let lane_height = semitone_height(canvas.height, &sequencer_settings);
+    let offset = [
+        (Action::NudgeLeft, Vec2::new(-time_step, 0.)),
+        (Action::NudgeRight, Vec2::new(time_step, 0.)),
+        (Action::NudgeUp, Vec2::new(0., lane_height)),
+        (Action::NudgeDown, Vec2::new(0., -lane_height)),
+        (Action::OctaveUp, Vec2::new(0., 12. * lane_height)),
+        (Action::OctaveDown, Vec2::new(0., -12. * lane_height)),
+    ]
+    .into_iter()
+    .filter(|(action, _)| keymap.just_pressed(*action, &keyboard_input))
+    .map(|(_, offset)| offset)
+    .sum::<Vec2>();
+
+    if offset == Vec2::ZERO {
+        return;
+    }
+    for entity in selected.entities.iter() {
+        if let Ok(mut transform) = note_query.get_mut(*entity) {
+            transform.translation += offset.extend(0.);
+        }
+    }
+}
+
+fn open_context_menu(
+    mut contexts: EguiContexts,
+    cursor: Res<CursorPosition>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut context_menu: ResMut<ContextMenu>,
+    mut selected: ResMut<Selected>,
+    notes_query: Query<(Entity, &Transform), With<Note>>,
+) {
+    if pointer_over_ui(&mut contexts) {
+        return;
+    }
+
+    if mouse_button_input.just_pressed(MouseButton::Left) {
+        context_menu.position = None;
+    }
+
+    if mouse_button_input.just_pressed(MouseButton::Right) {
+        let (Some(position), Some(world_position)) = (cursor.window, cursor.world) else {
+            return;
+        };
+
+        context_menu.position = Some(position);
+        context_menu.world_position = world_position;
+        context_menu.target = note_at(world_position, &notes_query);
+
+        if let Some(entity) = context_menu.target {
+            if !selected.contains(entity) {
+                selected.select_only(entity);
             }
         }
     }
 }
+
+#[allow(clippy::too_many_arguments)]
+fn context_menu(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut context_menu: ResMut<ContextMenu>,
+    mut selected: ResMut<Selected>,
+    active_layer: Res<ActiveLayer>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+    mut note_query: Query<(&Note, &mut Transform)>,
+    playhead_query: Query<(&Transform, &Playhead), Without<Note>>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    snap: Res<Snap>,
+    random_scene: Res<RandomScene>,
+) {
+    let Some(position) = context_menu.position else {
+        return;
+    };
+    let window = window_query.get_single().unwrap();
+    let beat_width = sequencer_settings.beat_width(canvas.width);
+    let octave_height = 12. * semitone_height(canvas.height, &sequencer_settings);
+    let mut close = false;
+
+    egui::Area::new("note_context_menu")
+        .fixed_pos(egui::pos2(position.x, window.height() - position.y))
+        .order(egui::Order::Foreground)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::menu(ui.style()).show(ui, |ui| match context_menu.target {
+                Some(target) => {
+                    if let Ok((_, transform)) = note_query.get(target) {
+                        let mut beats =
+                            note_beats(transform.scale.x, canvas.width, &sequencer_settings);
+                        let response = ui
+                            .horizontal(|ui| {
+                                ui.label("Duration");
+                                ui.add(
+                                    egui::DragValue::new(&mut beats)
+                                        .speed(0.05)
+                                        .clamp_range(MIN_NOTE_BEATS..=f32::MAX)
+                                        .suffix(" beats"),
+                                )
+                            })
+                            .inner;
+
+                        if response.changed() {
+                            let width = beats * beat_width;
+                            for entity in selected.entities.iter() {
+                                if let Ok((_, mut transform)) = note_query.get_mut(*entity) {
+                                    // Keep the note's start where it is.
+                                    transform.translation.x += (width - transform.scale.x) / 2.;
+                                    transform.scale.x = width;
+                                }
+                            }
+                        }
+                    }
+
+                    let mut transpose = None;
+                    if ui.button("Octave up").clicked() {
+                        transpose = Some(octave_height);
+                    }
+                    if ui.button("Octave down").clicked() {
+                        transpose = Some(-octave_height);
+                    }
+                    if let Some(offset) = transpose {
+                        for entity in selected.entities.iter() {
+                            if let Ok((_, mut transform)) = note_query.get_mut(*entity) {
+                                transform.translation.y += offset;
+                            }
+                        }
+                    }
+
+                    if ui.button("Duplicate").clicked() {
+                        let loop_width = active_loop_width(&active_layer, &playhead_query, &canvas);
+                        duplicate_selection(
+                            &mut commands,
+                            &mut selected,
+                            &note_query,
+                            loop_width,
+                            &canvas,
+                        );
+                        close = true;
+                    }
+                    if ui.button("Delete").clicked() {
+                        for entity in selected.entities.drain() {
+                            delete_note(&mut commands, &mut midi_out_note_off, entity);
+                        }
+                        close = true;
+                    }
+                }
+                None => {
+                    if ui.button("Add note").clicked() {
+                        let entity = spawn_note(
+                            &mut commands,
+                            new_note_translation(
+                                context_menu.world_position,
+                                random_scene.note_size,
+                                &snap,
+                                active_layer.0,
+                            ),
+                            random_scene.note_size,
+                        );
+                        selected.select_only(entity);
+                        close = true;
+                    }
+                }
+            });
+        });
+
+    if close {
+        context_menu.position = None;
+    }
+}
+
+fn spawn_resize_handles(mut commands: Commands) {
+    for edge in [NoteEdge::Left, NoteEdge::Right] {
+        commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(1., 1., 1.),
+                    custom_size: Some(Vec2::new(RESIZE_HANDLE_WIDTH, 1.)),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            })
+            .insert(ResizeHandle(edge));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_resize(
+    mut contexts: EguiContexts,
+    panning: Res<Panning>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    cursor: Res<CursorPosition>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut resizing: ResMut<Resizing>,
+    mut selected: ResMut<Selected>,
+    notes_query: Query<(Entity, &Transform), With<Note>>,
+) {
+    if pointer_captured(&mut contexts, &panning) {
+        return;
+    }
+
+    let mut window = window_query.get_single_mut().unwrap();
+    let edge = cursor
+        .world
+        .and_then(|position| edge_at(position, &notes_query));
+
+    let icon = if edge.is_some() || resizing.note.is_some() {
+        CursorIcon::EwResize
+    } else {
+        CursorIcon::Default
+    };
+    if window.cursor.icon != icon {
+        window.cursor.icon = icon;
+    }
+
+    if mouse_button_input.just_pressed(MouseButton::Left) {
+        if let Some((entity, _)) = edge {
+            info!("resizing note: {:?}", entity);
+            selected.select_only(entity);
+            resizing.note = edge;
+        }
+    }
+}
+
+fn resize_note(
+    cursor: Res<CursorPosition>,
+    snap: Res<Snap>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut resizing: ResMut<Resizing>,
+    mut note_query: Query<&mut Transform, With<Note>>,
+) {
+    let Some((entity, edge)) = resizing.note else {
+        return;
+    };
+
+    if !mouse_button_input.pressed(MouseButton::Left) {
+        resizing.note = None;
+        return;
+    }
+
+    let (Some(cursor_position), Ok(mut transform)) = (cursor.world, note_query.get_mut(entity))
+    else {
+        return;
+    };
+
+    let min_width = snap.time_step.unwrap_or(MIN_NOTE_WIDTH);
+    let edge_x = snap.x(cursor_position.x);
+    let left = transform.translation.x - transform.scale.x / 2.;
+    let right = transform.translation.x + transform.scale.x / 2.;
+
+    let (left, right) = match edge {
+        NoteEdge::Left => (edge_x.min(right - min_width), right),
+        NoteEdge::Right => (left, edge_x.max(left + min_width)),
+    };
+
+    transform.translation.x = (left + right) / 2.;
+    transform.scale.x = right - left;
+}
+
+fn resize_handles(
+    selected: Res<Selected>,
+    note_query: Query<&Transform, (With<Note>, Without<ResizeHandle>)>,
+    mut handle_query: Query<(&mut Transform, &mut Visibility, &ResizeHandle)>,
+) {
+    let note_transform = selected
+        .single()
+        .and_then(|entity| note_query.get(entity).ok());
+
+    for (mut transform, mut visibility, handle) in handle_query.iter_mut() {
+        let Some(note_transform) = note_transform else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        let offset = match handle.0 {
+            NoteEdge::Left => -note_transform.scale.x / 2.,
+            NoteEdge::Right => note_transform.scale.x / 2.,
+        };
+
+        *visibility = Visibility::Inherited;
+        transform.translation = note_transform.translation + Vec3::new(offset, 0., 0.5);
+        transform.scale.y = note_transform.scale.y;
+    }
+}
+
+fn spawn_marquee(mut commands: Commands) {
+    commands
+        .spawn(SpriteBundle {
+            transform: Transform::from_xyz(0., 0., 50.).with_scale(Vec3::ZERO),
+            sprite: Sprite {
+                color: Color::rgba(0.5, 0.7, 1., 0.2),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Marquee);
+}
+
+fn cursor_position(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    mut cursor: ResMut<CursorPosition>,
+) {
+    let window = window_query.get_single().unwrap();
+    let (camera, camera_transform) = camera_query.single();
+
+    cursor.window = window.cursor_position();
+    cursor.world = cursor.window.and_then(|position| {
+        camera.viewport_to_world_2d(camera_transform, position - viewport_origin(camera, window))
+    });
+}
@@ -1,29 +1,183 @@
-use bevy::{prelude::*, window::PrimaryWindow};
-use rand::random;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-use crate::NUMBER_OF_RANDOM_PLAYHEADS;
+use super::{
+    layer::{layer_of, Layers, SILENT_ALPHA},
+    mouse_input::Selected,
+    playhead::spawn_random_playheads,
+    random::{on_regenerate, Generated, SequencerRng},
+    scene::{random_startup, RandomScene},
+    sequence::{Canvas, GlobalSequencerSettings},
+    tuning::{LoadedTuning, TuningSettings},
+};
 
-use super::sequence::GlobalSequencerSettings;
-
-const NUMBER_OF_RANDOM_NOTES: usize = 3;
+const NOTE_COLOR: Color = Color::rgb(0., 1., 0.);
+/// Selected notes turn yellow.
+const SELECTED_NOTE_HUE: f32 = 60.0;
+/// Hue of layer 0, with each further layer turned by the golden angle so neighbours differ.
+const FIRST_LAYER_HUE: f32 = 120.0;
+const LAYER_HUE_STEP: f32 = 137.5;
+const NOTE_SATURATION: f32 = 0.8;
+const NOTE_LIGHTNESS: f32 = 0.45;
+/// Lightness a sounding note is raised by.
+const FLASH_LIGHTNESS: f32 = 0.45;
+/// How long the flash takes to fade once the note stops sounding.
+const FLASH_DECAY_SECONDS: f32 = 0.4;
 
 pub struct NotePlugin;
 
 impl Plugin for NotePlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_random_notes)
-            .add_system(note_pitch);
+        // Playheads draw from the random sequence first, so a seed always gives the same scene.
+        app.add_startup_system(
+            spawn_random_notes
+                .after(spawn_random_playheads)
+                .run_if(random_startup),
+        )
+        .add_system(
+            spawn_random_notes
+                .in_base_set(CoreSet::PreUpdate)
+                .after(spawn_random_playheads)
+                .run_if(on_regenerate()),
+        )
+        .init_resource::<NoteColors>()
+        .add_system(note_pitch)
+        .add_system(note_flash)
+        .add_system(note_color.after(note_pitch).after(note_flash));
     }
 }
 
+pub const DEFAULT_VELOCITY: u8 = 127;
+
 #[derive(Component)]
 pub struct Note {
+    /// The key the note's height stands for, in the layer's scale.
+    pub key: u8,
+    /// MIDI note sent, `key` retuned when a tuning is loaded.
     pub pitch: u8,
+    /// Pitch bend reaching a microtonal pitch from `pitch`, 0 being none.
+    pub bend: i16,
+    pub velocity: u8,
+    /// MIDI channel, 0 based. Ignored while MPE spreads notes over member channels.
+    pub channel: u8,
+    /// Chance of playing each time a playhead reaches it, from 0 to 1.
+    pub probability: f32,
+    pub condition: TrigCondition,
+    pub ratchet: Ratchet,
+}
+
+impl Default for Note {
+    fn default() -> Self {
+        Note {
+            key: 60,
+            pitch: 60,
+            bend: 0,
+            velocity: DEFAULT_VELOCITY,
+            channel: 0,
+            probability: 1.,
+            condition: TrigCondition::Always,
+            ratchet: Ratchet::default(),
+        }
+    }
+}
+
+/// Repeats of a note while a playhead crosses it, the first on reaching it. Repeats
+/// that would fall past the note's end are dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ratchet {
+    /// Hits in all, 1 being no repeats.
+    pub hits: u32,
+    /// Hits per beat.
+    pub rate: f32,
+    /// Added to the velocity with each hit.
+    pub velocity_step: i8,
+    /// Semitones added to the pitch with each hit.
+    pub pitch_step: i8,
+}
+
+impl Default for Ratchet {
+    fn default() -> Self {
+        Ratchet {
+            hits: 1,
+            rate: 4.,
+            velocity_step: 0,
+            pitch_step: 0,
+        }
+    }
+}
+
+impl Ratchet {
+    /// The hit due once a playhead is `travelled` into the note.
+    pub fn hit_at(&self, travelled: f32, beat_width: f32) -> u32 {
+        let spacing = beat_width / self.rate.max(f32::EPSILON);
+        ((travelled / spacing).max(0.) as u32).min(self.hits.max(1) - 1)
+    }
+
+    pub fn velocity(&self, velocity: u8, hit: u32) -> u8 {
+        (velocity as i32 + self.velocity_step as i32 * hit as i32).clamp(1, 127) as u8
+    }
+
+    pub fn pitch(&self, pitch: u8, hit: u32) -> u8 {
+        (pitch as i32 + self.pitch_step as i32 * hit as i32).clamp(0, 127) as u8
+    }
+}
+
+/// Which passes of the playhead a note plays on, counting the loops it has finished from
+/// 0 since the last stop.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrigCondition {
+    #[default]
+    Always,
+    /// The first pass and every `n`th after it.
+    Every(u32),
+    /// Only the first pass.
+    First,
+    /// Every pass but every `n`th.
+    Skip(u32),
+    /// Only when the note the same playhead reached just before played.
+    Previous,
+}
+
+impl TrigCondition {
+    pub const ALL: [TrigCondition; 5] = [
+        TrigCondition::Always,
+        TrigCondition::Every(2),
+        TrigCondition::First,
+        TrigCondition::Skip(2),
+        TrigCondition::Previous,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrigCondition::Always => "Always",
+            TrigCondition::Every(_) => "Every nth pass",
+            TrigCondition::First => "First pass",
+            TrigCondition::Skip(_) => "Skip every nth",
+            TrigCondition::Previous => "After a played note",
+        }
+    }
+
+    pub fn holds(&self, pass: u32, previous_played: bool) -> bool {
+        match *self {
+            TrigCondition::Always => true,
+            TrigCondition::Every(n) => pass.is_multiple_of(n.max(1)),
+            TrigCondition::First => pass == 0,
+            TrigCondition::Skip(n) => !(pass + 1).is_multiple_of(n.max(1)),
+            TrigCondition::Previous => previous_played,
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct Collider {
     pub state: CollisionState,
+    /// Whether the note played this pass, rather than its probability or condition
+    /// keeping it silent.
+    pub played: bool,
+    /// The ratchet hit sounding, 0 being the first.
+    pub hit: u32,
 }
 
 pub enum CollisionState {
@@ -33,79 +187,306 @@ pub enum CollisionState {
     CollisionEnd,
 }
 
+/// Brightness of a note's highlight: 1 while it sounds, then fading to 0.
+#[derive(Component, Default)]
+pub struct Flash(pub f32);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoteColorKey {
+    #[default]
+    Layer,
+    /// By pitch class, around the colour wheel.
+    Pitch,
+}
+
+impl NoteColorKey {
+    pub const ALL: [NoteColorKey; 2] = [NoteColorKey::Layer, NoteColorKey::Pitch];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoteColorKey::Layer => "Layer",
+            NoteColorKey::Pitch => "Pitch",
+        }
+    }
+}
+
+/// What note colours tell apart.
+#[derive(Resource, Default, Debug)]
+pub struct NoteColors {
+    pub key: NoteColorKey,
+}
+
+impl Collider {
+    /// Whether a playhead holds the note and it played.
+    pub fn sounding(&self) -> bool {
+        self.played
+            && matches!(
+                self.state,
+                CollisionState::CollisionStart | CollisionState::CollisionContinue
+            )
+    }
+}
+
 impl Default for Collider {
     fn default() -> Self {
         Collider {
             state: CollisionState::NoCollision,
+            played: false,
+            hit: 0,
         }
     }
 }
 
 pub fn spawn_random_notes(
     mut commands: Commands,
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    canvas: Res<Canvas>,
+    random_scene: Res<RandomScene>,
+    mut sequencer_rng: ResMut<SequencerRng>,
 ) {
-    let window = window_query.get_single().unwrap();
-
-    let random_positions = (0..NUMBER_OF_RANDOM_NOTES)
-        .map(|_| {
-            Vec2::new(
-                random::<f32>() * window.width(),
-                random::<f32>() * window.height(),
-            )
-        })
+    let random_positions = (0..random_scene.notes)
+        .map(|_| Vec2::new(sequencer_rng.gen::<f32>(), sequencer_rng.gen::<f32>()) * canvas.size())
         .collect::<Vec<Vec2>>();
 
-    for playhead in 0..NUMBER_OF_RANDOM_PLAYHEADS {
-        for note in 0..NUMBER_OF_RANDOM_NOTES {
-            let random_x = random_positions[note].x;
-            let random_y = random_positions[note].y;
-
-            commands
-                .spawn(SpriteBundle {
-                    transform: Transform {
-                        translation: Vec3::new(random_x, random_y, playhead as f32),
-                        scale: Vec3::new(120., 20., 0.),
-                        ..default()
-                    },
-                    sprite: Sprite {
-                        color: Color::rgb(0., 1., 0.),
-                        ..default()
-                    },
-                    ..default()
-                })
-                .insert(Note { pitch: 60 })
-                .insert(Collider { ..default() });
+    for playhead in 0..random_scene.playheads {
+        for position in random_positions.iter() {
+            let entity = spawn_note(
+                &mut commands,
+                position.extend(playhead as f32),
+                random_scene.note_size,
+            );
+            commands.entity(entity).insert(Generated);
         }
     }
 }
 
+pub fn spawn_note(commands: &mut Commands, translation: Vec3, size: Vec2) -> Entity {
+    commands
+        .spawn(SpriteBundle {
+            transform: Transform {
+                translation,
+                scale: size.extend(0.),
+                ..default()
+            },
+            sprite: Sprite {
+                color: NOTE_COLOR,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Note::default())
+        .insert(Collider { ..default() })
+        .insert(Flash::default())
+        .id()
+}
+
+/// Pitch follows the note's height on the canvas, never the window, so resizing
+/// the window doesn't transpose anything. It is then snapped into the layer's key and,
+/// with a tuning loaded, retuned to the nearest MIDI note plus pitch bend.
 pub fn note_pitch(
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    canvas: Res<Canvas>,
+    layers: Res<Layers>,
     mut note_query: Query<(&mut Note, &Transform), With<Note>>,
     sequencer_settings: Res<GlobalSequencerSettings>,
+    tuning_settings: Res<TuningSettings>,
+    loaded_tuning: Res<LoadedTuning>,
 ) {
-    let window = window_query.get_single().unwrap();
-    let window_min = 0. as f32;
-    let window_max = window.height();
+    let canvas_min = 0.;
+    let canvas_max = canvas.height;
     let min = sequencer_settings.pitch_min;
     let max = sequencer_settings.pitch_max;
 
     for (mut note, note_transform) in note_query.iter_mut() {
         let note_y_position_as_midi = map_to_midi_range(
             note_transform.translation.y,
-            window_min,
-            window_max,
+            canvas_min,
+            canvas_max,
             min,
             max,
         );
 
-        note.pitch = note_y_position_as_midi;
+        let layer = layers.get(layer_of(note_transform));
+        let (scale, root) = sequencer_settings.scale_for(&layer);
+
+        let key = scale.quantize(note_y_position_as_midi, root);
+        let (pitch, bend) = loaded_tuning
+            .0
+            .as_ref()
+            .and_then(|tuning| tuning.midi_note(key, tuning_settings.pitch_bend_range))
+            .unwrap_or((key, 0));
+
+        // Only an actual change counts, so edits to notes can be told apart.
+        if (note.key, note.pitch, note.bend) != (key, pitch, bend) {
+            note.key = key;
+            note.pitch = pitch;
+            note.bend = bend;
+        }
+    }
+}
+
+/// Lights notes up while they sound, fading out after.
+pub fn note_flash(time: Res<Time>, mut note_query: Query<(&Collider, &mut Flash)>) {
+    for (collider, mut flash) in note_query.iter_mut() {
+        if collider.sounding() {
+            if flash.0 != 1. {
+                flash.0 = 1.;
+            }
+        } else if flash.0 > 0. {
+            flash.0 = (flash.0 - time.delta_seconds() / FLASH_DECAY_SECONDS).max(0.);
+        }
+    }
+}
+
+/// Notes are coloured by layer or pitch, brightened by their flash. Notes on silenced
+/// layers are dimmed.
+pub fn note_color(
+    selected: Res<Selected>,
+    layers: Res<Layers>,
+    note_colors: Res<NoteColors>,
+    mut note_query: Query<(Entity, &Note, &Transform, &Flash, &mut Sprite)>,
+) {
+    for (entity, note, transform, flash, mut sprite) in note_query.iter_mut() {
+        let hue = if selected.contains(entity) {
+            SELECTED_NOTE_HUE
+        } else {
+            match note_colors.key {
+                NoteColorKey::Layer => {
+                    FIRST_LAYER_HUE + layer_of(transform) as f32 * LAYER_HUE_STEP
+                }
+                NoteColorKey::Pitch => (note.key % 12) as f32 * 30.,
+            }
+        };
+        let lightness = NOTE_LIGHTNESS + flash.0 * FLASH_LIGHTNESS;
+        let alpha = if layers.audible(layer_of(transform)) {
+            1.
+        } else {
+            SILENT_ALPHA
+        };
+
+        let color = Color::hsla(hue % 360., NOTE_SATURATION, lightness, alpha);
+        if sprite.color != color {
+            sprite.color = color;
+        }
     }
 }
 
+/// Vertical distance between two adjacent pitches on a canvas of `height`.
+pub fn semitone_height(height: f32, sequencer_settings: &GlobalSequencerSettings) -> f32 {
+    height / (sequencer_settings.pitch_max - sequencer_settings.pitch_min) as f32
+}
+
 fn map_to_midi_range(value: f32, old_min: f32, old_max: f32, new_min: u8, new_max: u8) -> u8 {
     let midi_value = ((value - old_min) * (new_max as f32 - new_min as f32)) / (old_max - old_min)
         + new_min as f32;
-    midi_value.max(0.0).min(127.0) as u8
+    midi_value.clamp(0.0, 127.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions_hold_on_their_passes() {
+        let cases = [
+            (TrigCondition::Always, [true, true, true, true, true, true]),
+            (
+                TrigCondition::Every(2),
+                [true, false, true, false, true, false],
+            ),
+            (
+                TrigCondition::Every(3),
+                [true, false, false, true, false, false],
+            ),
+            (
+                TrigCondition::First,
+                [true, false, false, false, false, false],
+            ),
+            (
+                TrigCondition::Skip(2),
+                [true, false, true, false, true, false],
+            ),
+            (
+                TrigCondition::Skip(3),
+                [true, true, false, true, true, false],
+            ),
+        ];
+        for (condition, expected) in cases {
+            for (pass, expected) in expected.into_iter().enumerate() {
+                assert_eq!(
+                    condition.holds(pass as u32, false),
+                    expected,
+                    "{:?} on pass {}",
+                    condition,
+                    pass
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_counts_act_as_one() {
+        for pass in 0..4 {
+            assert!(TrigCondition::Every(0).holds(pass, false));
+            assert!(!TrigCondition::Skip(0).holds(pass, false));
+        }
+    }
+
+    #[test]
+    fn previous_follows_the_note_before() {
+        assert!(TrigCondition::Previous.holds(3, true));
+        assert!(!TrigCondition::Previous.holds(0, false));
+    }
+
+    #[test]
+    fn ratchet_hits_are_spaced_by_rate() {
+        let ratchet = Ratchet {
+            hits: 4,
+            rate: 4.,
+            ..default()
+        };
+        let cases = [
+            (-5., 0),
+            (0., 0),
+            (24.9, 0),
+            (25., 1),
+            (74.9, 2),
+            (75., 3),
+            (500., 3),
+        ];
+        for (travelled, hit) in cases {
+            assert_eq!(ratchet.hit_at(travelled, 100.), hit, "at {}", travelled);
+        }
+    }
+
+    #[test]
+    fn degenerate_ratchets_only_hit_once() {
+        let cases = [(0, 4.), (1, 4.), (4, 0.), (4, 1e-9), (4, -2.)];
+        for (hits, rate) in cases {
+            let ratchet = Ratchet {
+                hits,
+                rate,
+                ..default()
+            };
+            assert_eq!(ratchet.hit_at(1000., 100.), 0, "{:?}", ratchet);
+        }
+    }
+
+    #[test]
+    fn ratchet_steps_stay_in_midi_range() {
+        let ramp = |velocity_step, pitch_step| Ratchet {
+            velocity_step,
+            pitch_step,
+            ..default()
+        };
+        let cases = [
+            (ramp(10, 12), 100, 60, 0, 100, 60),
+            (ramp(10, 12), 100, 60, 2, 120, 84),
+            (ramp(10, 12), 100, 120, 3, 127, 127),
+            (ramp(-50, -12), 100, 60, 1, 50, 48),
+            (ramp(-50, -12), 100, 5, 3, 1, 0),
+        ];
+        for (ratchet, velocity, pitch, hit, expected_velocity, expected_pitch) in cases {
+            assert_eq!(ratchet.velocity(velocity, hit), expected_velocity);
+            assert_eq!(ratchet.pitch(pitch, hit), expected_pitch);
+        }
+    }
 }
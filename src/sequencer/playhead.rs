@@ -1,11 +1,31 @@
-use bevy::{prelude::*, sprite::collide_aabb::collide, window::PrimaryWindow};
-use rand::Rng;
+use bevy::{
+    prelude::*,
+    sprite::collide_aabb::collide,
+    utils::{HashMap, HashSet},
+};
+use bevy_egui::EguiContexts;
+use rand::{rngs::StdRng, Rng};
+use serde::{Deserialize, Serialize};
 
-use crate::{sequencer::note::CollisionState, NUMBER_OF_RANDOM_PLAYHEADS};
+use super::{
+    keymap::{Action, Keymap},
+    layer::{layer_of, Layers, SILENT_ALPHA},
+    note::{Collider, CollisionState, Note},
+    random::{clear_generated, on_regenerate, Generated, SequencerRng},
+    scene::{random_startup, RandomScene},
+    sequence::{Canvas, GlobalSequencerSettings, Transport},
+};
 
-use super::note::{Collider, Note};
-
-const DEFAULT_PLAYHEAD_SPEED: f32 = 300.0;
+/// Random playhead rates are multiples of this where the range allows, so they stay on
+/// the beat grid.
+const RANDOM_RATE_STEP: f32 = 0.25;
+const BRACKET_WIDTH: f32 = 3.0;
+const BRACKET_TICK_LENGTH: f32 = 16.0;
+const BRACKET_ALPHA: f32 = 0.6;
+/// Tells the stream note probabilities are rolled from apart from the seed's others.
+const PROBABILITY_STREAM: u64 = 0x7072_6f62;
+/// Loop regions are never narrower than this.
+pub const MIN_LOOP_WIDTH: f32 = 16.0;
 
 pub struct PlayheadPlugin;
 
@@ -13,9 +33,20 @@ impl Plugin for PlayheadPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<NoteOnEvent>()
             .add_event::<NoteOffEvent>()
-            .add_startup_system(spawn_random_playheads)
+            .add_event::<Rewind>()
+            .add_startup_system(spawn_random_playheads.run_if(random_startup))
+            .add_system(
+                spawn_random_playheads
+                    .in_base_set(CoreSet::PreUpdate)
+                    .after(clear_generated)
+                    .run_if(on_regenerate()),
+            )
+            .add_system(transport_shortcuts.before(playhead_movement))
             .add_system(playhead_movement)
-            .add_system(check_for_collisions);
+            .add_system(playhead_height)
+            .add_system(playhead_color)
+            .add_system(loop_brackets.after(playhead_movement))
+            .add_system(check_for_collisions.after(playhead_movement));
         // .add_system(note_struck)
     }
 }
@@ -24,7 +55,38 @@ impl Plugin for PlayheadPlugin {
 pub struct Playhead {
     pub direction: PlayheadDirection,
     pub current_direction: PlayheadDirection,
-    pub speed: f32,
+    /// Loops played per loop at the tempo, so 2 crosses the canvas twice as fast.
+    pub rate: f32,
+    /// How far into its loop, along its direction, the playhead starts.
+    pub start: f32,
+    pub color: Color,
+    pub muted: bool,
+    /// While any playhead is soloed, only soloed ones play.
+    pub solo: bool,
+    /// Loops over the whole canvas when not set.
+    pub region: Option<LoopRegion>,
+    /// Loops finished since the last rewind, which notes' conditions count.
+    pub passes: u32,
+}
+
+/// Part of the canvas a playhead loops over, in canvas coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LoopRegion {
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LoopEdge {
+    Start,
+    End,
+}
+
+/// Draws one end of a playhead's loop region.
+#[derive(Component)]
+struct LoopBracket {
+    playhead: Entity,
+    edge: LoopEdge,
 }
 
 impl Default for Playhead {
@@ -32,97 +94,249 @@ impl Default for Playhead {
         Playhead {
             direction: PlayheadDirection::Right,
             current_direction: PlayheadDirection::Right,
-            speed: DEFAULT_PLAYHEAD_SPEED,
+            rate: 1.,
+            start: 0.,
+            color: Color::rgb(1., 0., 0.),
+            muted: false,
+            solo: false,
+            region: None,
+            passes: 0,
         }
     }
 }
 
+impl Playhead {
+    /// Where the loop region starts and ends on the canvas.
+    pub fn bounds(&self, canvas: &Canvas) -> (f32, f32) {
+        match self.region {
+            Some(region) => (
+                region.start.clamp(0., canvas.width),
+                region.end.clamp(0., canvas.width),
+            ),
+            None => (0., canvas.width),
+        }
+    }
+
+    /// Moves the playhead back to where it starts its loop.
+    pub fn rewind(&mut self, transform: &mut Transform, canvas: &Canvas) {
+        let (begin, end) = self.bounds(canvas);
+        transform.translation.x = match self.direction {
+            PlayheadDirection::Left => (end - self.start).max(begin),
+            _ => (begin + self.start).min(end),
+        };
+        self.current_direction = self.direction.initial();
+        self.passes = 0;
+    }
+
+    /// The loop region with one end moved to `x`, at least `MIN_LOOP_WIDTH` from the
+    /// other.
+    pub fn moved_loop_edge(&self, edge: LoopEdge, x: f32, canvas: &Canvas) -> LoopRegion {
+        let (begin, end) = self.bounds(canvas);
+        match edge {
+            LoopEdge::Start => LoopRegion {
+                start: x.min(end - MIN_LOOP_WIDTH).max(0.),
+                end,
+            },
+            LoopEdge::End => LoopRegion {
+                start: begin,
+                end: x.max(begin + MIN_LOOP_WIDTH).min(canvas.width),
+            },
+        }
+    }
+
+    pub fn audible(&self, any_solo: bool) -> bool {
+        !self.muted && (self.solo || !any_solo)
+    }
+
+    /// Canvas units per second, following the tempo.
+    pub fn speed(&self, loop_speed: f32) -> f32 {
+        self.rate * loop_speed
+    }
+}
+
+/// Speed at which a playhead crosses the canvas once per loop at the current tempo.
+pub fn loop_speed(
+    canvas: &Canvas,
+    sequencer_settings: &GlobalSequencerSettings,
+    transport: &Transport,
+) -> f32 {
+    let loop_seconds = sequencer_settings.beats_per_loop() as f32 * 60. / transport.tempo;
+    canvas.width / loop_seconds
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PlayheadDirection {
+    #[default]
     Right,
     Left,
     Pendulum,
 }
 
-impl Default for PlayheadDirection {
-    fn default() -> Self {
-        PlayheadDirection::Right
+impl PlayheadDirection {
+    pub const ALL: [PlayheadDirection; 3] = [
+        PlayheadDirection::Right,
+        PlayheadDirection::Left,
+        PlayheadDirection::Pendulum,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayheadDirection::Right => "Right",
+            PlayheadDirection::Left => "Left",
+            PlayheadDirection::Pendulum => "Pendulum",
+        }
+    }
+
+    /// The way a playhead going in this direction sets off.
+    pub fn initial(&self) -> PlayheadDirection {
+        match self {
+            PlayheadDirection::Left => PlayheadDirection::Left,
+            _ => PlayheadDirection::Right,
+        }
     }
 }
 
-pub struct NoteOnEvent(pub Entity);
+/// Strikes a note, the number saying which of its ratchet hits this is.
+pub struct NoteOnEvent(pub Entity, pub u32);
 
 pub struct NoteOffEvent(pub Entity);
 
+/// Stop rewound the playheads, so notes count their passes from the start again.
+pub struct Rewind;
+
 pub fn spawn_random_playheads(
     mut commands: Commands,
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    canvas: Res<Canvas>,
+    random_scene: Res<RandomScene>,
+    mut sequencer_rng: ResMut<SequencerRng>,
 ) {
-    let window = window_query.get_single().unwrap();
-    let height = window.height();
-    let mut rng = rand::thread_rng();
-    let lower_bound = 100.;
-    let upper_bound = 300.;
-
-    for playhead in 0..NUMBER_OF_RANDOM_PLAYHEADS {
-        let speed = rng.gen_range(lower_bound..=upper_bound);
-        let z = playhead as f32;
-
-        commands
-            .spawn(SpriteBundle {
-                transform: Transform {
-                    translation: Vec3::new(0., height / 2., z),
-                    scale: Vec3::new(5.0, height, 0.0),
-                    ..default()
-                },
-                sprite: Sprite {
-                    color: Color::rgb(1., 0., 0.),
-                    ..default()
-                },
+    let (min, max) = (*random_scene.rate.start(), *random_scene.rate.end());
+    for playhead in 0..random_scene.playheads {
+        let rate = sequencer_rng.gen_range(min..=max);
+        let rate = ((rate / RANDOM_RATE_STEP).round() * RANDOM_RATE_STEP)
+            .max(RANDOM_RATE_STEP)
+            .clamp(min, max);
+        let entity = spawn_playhead(
+            &mut commands,
+            &canvas,
+            Vec3::new(0., 0., playhead as f32),
+            Playhead { rate, ..default() },
+        );
+        commands.entity(entity).insert(Generated);
+    }
+}
+
+/// Spawns a playhead at `translation`, stretched over the height of the canvas.
+pub fn spawn_playhead(
+    commands: &mut Commands,
+    canvas: &Canvas,
+    translation: Vec3,
+    playhead: Playhead,
+) -> Entity {
+    commands
+        .spawn(SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(translation.x, canvas.height / 2., translation.z),
+                scale: Vec3::new(5.0, canvas.height, 0.0),
                 ..default()
-            })
-            .insert(Playhead {
-                speed: speed,
+            },
+            sprite: Sprite {
+                color: playhead.color,
                 ..default()
-            });
+            },
+            ..default()
+        })
+        .insert(playhead)
+        .id()
+}
+
+/// Enter plays or pauses, Escape stops and rewinds, by default.
+fn transport_shortcuts(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    canvas: Res<Canvas>,
+    mut transport: ResMut<Transport>,
+    mut playhead_query: Query<(&mut Transform, &mut Playhead)>,
+    mut rewinds: EventWriter<Rewind>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    if keymap.just_pressed(Action::PlayPause, &keyboard_input) {
+        transport.playing = !transport.playing;
+    }
+    if keymap.just_pressed(Action::Stop, &keyboard_input) {
+        transport.playing = false;
+        for (mut transform, mut playhead) in playhead_query.iter_mut() {
+            playhead.rewind(&mut transform, &canvas);
+        }
+        rewinds.send(Rewind);
     }
 }
 
 pub fn playhead_movement(
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    transport: Res<Transport>,
     mut playhead_query: Query<(&mut Transform, &mut Playhead)>,
     time: Res<Time>,
 ) {
-    let window = window_query.get_single().unwrap();
+    if !transport.playing {
+        return;
+    }
+    let loop_speed = loop_speed(&canvas, &sequencer_settings, &transport);
 
     for (mut transform, mut playhead) in playhead_query.iter_mut() {
+        // Playheads outside their region, after it was moved, jump back into it.
+        let (begin, end) = playhead.bounds(&canvas);
+        let step = playhead.speed(loop_speed) * time.delta_seconds();
+
         match &playhead.direction {
             PlayheadDirection::Right => {
-                transform.translation.x += playhead.speed * time.delta_seconds();
+                transform.translation.x += step;
 
-                if transform.translation.x > window.width() {
-                    transform.translation.x = 0.;
+                if transform.translation.x > end {
+                    playhead.passes += 1;
+                }
+                if transform.translation.x > end || transform.translation.x < begin {
+                    transform.translation.x = begin;
                 }
             }
             PlayheadDirection::Left => {
-                transform.translation.x -= playhead.speed * time.delta_seconds();
+                transform.translation.x -= step;
 
-                if transform.translation.x > 0. {
-                    transform.translation.x = window.width();
+                if transform.translation.x < begin {
+                    playhead.passes += 1;
+                }
+                if transform.translation.x < begin || transform.translation.x > end {
+                    transform.translation.x = end;
                 }
             }
             PlayheadDirection::Pendulum => match &playhead.current_direction {
                 PlayheadDirection::Right => {
-                    transform.translation.x += playhead.speed * time.delta_seconds();
+                    transform.translation.x += step;
 
-                    if transform.translation.x > window.width() {
+                    if transform.translation.x < begin {
+                        transform.translation.x = begin;
+                    }
+                    if transform.translation.x > end {
+                        transform.translation.x = end;
                         playhead.current_direction = PlayheadDirection::Left;
                     }
                 }
                 PlayheadDirection::Left => {
-                    transform.translation.x -= playhead.speed * time.delta_seconds();
+                    transform.translation.x -= step;
 
-                    if transform.translation.x < 0. {
+                    if transform.translation.x > end {
+                        transform.translation.x = end;
+                    }
+                    // Back where it set off, so a pass there and back.
+                    if transform.translation.x < begin {
+                        transform.translation.x = begin;
                         playhead.current_direction = PlayheadDirection::Right;
+                        playhead.passes += 1;
                     }
                 }
                 PlayheadDirection::Pendulum => {}
@@ -131,52 +345,325 @@ pub fn playhead_movement(
     }
 }
 
+/// Playheads span the full height of the canvas.
+pub fn playhead_height(
+    canvas: Res<Canvas>,
+    mut playhead_query: Query<&mut Transform, With<Playhead>>,
+) {
+    if !canvas.is_changed() {
+        return;
+    }
+
+    for mut transform in playhead_query.iter_mut() {
+        transform.translation.y = canvas.height / 2.;
+        transform.scale.y = canvas.height;
+    }
+}
+
+fn bracket_translation(
+    edge: LoopEdge,
+    playhead: &Playhead,
+    playhead_transform: &Transform,
+    canvas: &Canvas,
+) -> Vec3 {
+    let (begin, end) = playhead.bounds(canvas);
+    let x = match edge {
+        LoopEdge::Start => begin,
+        LoopEdge::End => end,
+    };
+    Vec3::new(
+        x,
+        canvas.height / 2.,
+        playhead_transform.translation.z + 0.1,
+    )
+}
+
+fn bracket_color(playhead: &Playhead) -> Color {
+    *playhead
+        .color
+        .clone()
+        .set_a(playhead.color.a() * BRACKET_ALPHA)
+}
+
+fn spawn_loop_bracket(
+    commands: &mut Commands,
+    canvas: &Canvas,
+    translation: Vec3,
+    color: Color,
+    playhead: Entity,
+    edge: LoopEdge,
+) {
+    // The ticks point into the region.
+    let inward = match edge {
+        LoopEdge::Start => 1.,
+        LoopEdge::End => -1.,
+    };
+    let tick_x = inward * (BRACKET_TICK_LENGTH - BRACKET_WIDTH) / 2.;
+
+    commands
+        .spawn(SpatialBundle::from_transform(Transform::from_translation(
+            translation,
+        )))
+        .insert(LoopBracket { playhead, edge })
+        .with_children(|parent| {
+            parent.spawn(SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(BRACKET_WIDTH, canvas.height)),
+                    ..default()
+                },
+                ..default()
+            });
+            for y in [-1., 1.] {
+                parent.spawn(SpriteBundle {
+                    transform: Transform::from_xyz(
+                        tick_x,
+                        y * (canvas.height - BRACKET_WIDTH) / 2.,
+                        0.,
+                    ),
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(Vec2::new(BRACKET_TICK_LENGTH, BRACKET_WIDTH)),
+                        ..default()
+                    },
+                    ..default()
+                });
+            }
+        });
+}
+
+/// Keeps a bracket at each end of every playhead's loop region, in its colour.
+fn loop_brackets(
+    mut commands: Commands,
+    canvas: Res<Canvas>,
+    playhead_query: Query<(Entity, &Transform, &Playhead)>,
+    mut bracket_query: Query<(Entity, &LoopBracket, &mut Transform, &Children), Without<Playhead>>,
+    mut sprite_query: Query<&mut Sprite, Without<Playhead>>,
+) {
+    let mut placed = HashSet::new();
+
+    for (entity, bracket, mut transform, children) in bracket_query.iter_mut() {
+        let Ok((_, playhead_transform, playhead)) = playhead_query.get(bracket.playhead) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        // Brackets are as tall as the canvas they were spawned on.
+        if canvas.is_changed() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        placed.insert((bracket.playhead, bracket.edge));
+
+        let translation = bracket_translation(bracket.edge, playhead, playhead_transform, &canvas);
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+
+        let color = bracket_color(playhead);
+        for child in children.iter() {
+            if let Ok(mut sprite) = sprite_query.get_mut(*child) {
+                if sprite.color != color {
+                    sprite.color = color;
+                }
+            }
+        }
+    }
+
+    for (entity, transform, playhead) in playhead_query.iter() {
+        for edge in [LoopEdge::Start, LoopEdge::End] {
+            if !placed.contains(&(entity, edge)) {
+                let translation = bracket_translation(edge, playhead, transform, &canvas);
+                spawn_loop_bracket(
+                    &mut commands,
+                    &canvas,
+                    translation,
+                    bracket_color(playhead),
+                    entity,
+                    edge,
+                );
+            }
+        }
+    }
+}
+
+/// Dims playheads that don't play, or whose layer doesn't.
+pub fn playhead_color(
+    layers: Res<Layers>,
+    mut playhead_query: Query<(&Playhead, &Transform, &mut Sprite)>,
+) {
+    let any_solo = playhead_query.iter().any(|(playhead, _, _)| playhead.solo);
+
+    for (playhead, transform, mut sprite) in playhead_query.iter_mut() {
+        let color = if playhead.audible(any_solo) && layers.audible(layer_of(transform)) {
+            playhead.color
+        } else {
+            *playhead
+                .color
+                .clone()
+                .set_a(playhead.color.a() * SILENT_ALPHA)
+        };
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+/// A note on an audible layer plays while any audible playhead on its layer overlaps it,
+/// so muting or removing a playhead ends the notes it holds. Each time one is reached its
+/// probability and condition decide whether it plays at all, and its ratchet repeats it
+/// as the playhead travels across. Nothing plays while the transport is paused.
+#[allow(clippy::too_many_arguments)]
 pub fn check_for_collisions(
     mut midi_out_note_on: EventWriter<NoteOnEvent>,
     mut midi_out_note_off: EventWriter<NoteOffEvent>,
-    playhead_query: Query<&Transform, With<Playhead>>,
-    mut collider_query: Query<(Entity, &Transform, &mut Collider), With<Note>>,
+    mut rewinds: EventReader<Rewind>,
+    mut removed_playheads: RemovedComponents<Playhead>,
+    canvas: Res<Canvas>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    transport: Res<Transport>,
+    layers: Res<Layers>,
+    sequencer_rng: Res<SequencerRng>,
+    playhead_query: Query<(Entity, &Transform, &Playhead)>,
+    mut collider_query: Query<(Entity, &Note, &Transform, &mut Collider)>,
+    mut last_played: Local<HashMap<Entity, bool>>,
+    mut was_playing: Local<bool>,
+    mut probability_rng: Local<Option<(u64, StdRng)>>,
 ) {
-    for playhead_transform in playhead_query.iter() {
-        for (collider_entity, collider_transform, mut collider) in collider_query.iter_mut() {
-            if playhead_transform.translation.z != collider_transform.translation.z {
-                continue;
+    // Rolls come from the seed, starting over on Stop so playback repeats, but from a
+    // stream of their own so playing never changes what the seed builds.
+    let seed = sequencer_rng.seed();
+    if rewinds.iter().count() > 0 {
+        last_played.clear();
+        *probability_rng = None;
+    }
+    if matches!(*probability_rng, Some((from, _)) if from != seed) {
+        *probability_rng = None;
+    }
+    // A removed playhead's id may come back on a new one, which reached nothing yet.
+    for entity in removed_playheads.iter() {
+        last_played.remove(&entity);
+    }
+
+    // Pausing releases whatever sounds, and resuming strikes it again.
+    let resumed = transport.playing && !*was_playing;
+    if !transport.playing {
+        if *was_playing {
+            for (entity, _, _, collider) in collider_query.iter() {
+                if collider.sounding() {
+                    midi_out_note_off.send(NoteOffEvent(entity));
+                }
+            }
+        }
+        *was_playing = false;
+        return;
+    }
+    *was_playing = true;
+
+    let any_solo = playhead_query.iter().any(|(_, _, playhead)| playhead.solo);
+    let beat_width = sequencer_settings.beat_width(canvas.width);
+    // Notes reached this frame, with the playhead reaching them and its pass.
+    let mut reached = Vec::new();
+
+    for (collider_entity, note, collider_transform, mut collider) in collider_query.iter_mut() {
+        let layer = layer_of(collider_transform);
+        let overlapping = playhead_query
+            .iter()
+            .find(|(_, playhead_transform, playhead)| {
+                layers.audible(layer)
+                    && playhead.audible(any_solo)
+                    && playhead_transform.translation.z == collider_transform.translation.z
+                    && collide(
+                        playhead_transform.translation,
+                        playhead_transform.scale.truncate(),
+                        collider_transform.translation,
+                        collider_transform.scale.truncate(),
+                    )
+                    .is_some()
+            });
+        let collision = overlapping.is_some();
+
+        // How far into the note the playhead is, from the edge it came in by.
+        let half_width = collider_transform.scale.x / 2.;
+        let (left, right) = (
+            collider_transform.translation.x - half_width,
+            collider_transform.translation.x + half_width,
+        );
+        let travelled = |playhead_transform: &Transform, playhead: &Playhead| {
+            let x = playhead_transform.translation.x;
+            match playhead.current_direction {
+                PlayheadDirection::Left => right - x,
+                _ => x - left,
             }
+        };
+        let hit = overlapping.map_or(0, |(_, playhead_transform, playhead)| {
+            // A beat of the playhead's travel, so hits keep time at any rate.
+            let travelled = travelled(playhead_transform, playhead);
+            note.ratchet.hit_at(travelled, beat_width * playhead.rate)
+        });
 
-            let collision = collide(
-                playhead_transform.translation,
-                playhead_transform.scale.truncate(),
-                collider_transform.translation,
-                collider_transform.scale.truncate(),
-            );
-
-            match collider.state {
-                CollisionState::NoCollision => {
-                    if collision.is_some() {
-                        collider.state = CollisionState::CollisionStart;
-                        midi_out_note_on.send(NoteOnEvent(collider_entity));
-                    }
+        match collider.state {
+            CollisionState::NoCollision => {
+                if let Some((playhead_entity, _, playhead)) = overlapping {
+                    collider.state = CollisionState::CollisionStart;
+                    collider.played = false;
+                    collider.hit = 0;
+                    // Where along the playhead's way the note starts, earlier first.
+                    let order = match playhead.current_direction {
+                        PlayheadDirection::Left => -right,
+                        _ => left,
+                    };
+                    reached.push((
+                        playhead_entity,
+                        order,
+                        note.key,
+                        collider_entity,
+                        playhead.passes,
+                    ));
                 }
-                CollisionState::CollisionStart => {
-                    if collision.is_some() {
-                        collider.state = CollisionState::CollisionContinue;
-                    } else {
+            }
+            CollisionState::CollisionStart | CollisionState::CollisionContinue => {
+                if !collision {
+                    if collider.played {
                         midi_out_note_off.send(NoteOffEvent(collider_entity));
-                        collider.state = CollisionState::CollisionEnd;
                     }
-                }
-                CollisionState::CollisionContinue => {
-                    if collision.is_none() {
+                    collider.state = CollisionState::CollisionEnd;
+                } else {
+                    collider.state = CollisionState::CollisionContinue;
+                    if collider.played && (resumed || hit > collider.hit) {
+                        collider.hit = collider.hit.max(hit);
                         midi_out_note_off.send(NoteOffEvent(collider_entity));
-                        collider.state = CollisionState::CollisionEnd;
+                        midi_out_note_on.send(NoteOnEvent(collider_entity, collider.hit));
                     }
                 }
-                CollisionState::CollisionEnd => {
-                    if collision.is_none() {
-                        collider.state = CollisionState::NoCollision;
-                    }
+            }
+            CollisionState::CollisionEnd => {
+                if !collision {
+                    collider.state = CollisionState::NoCollision;
                 }
             }
         }
     }
+
+    // Each playhead's notes in the order it meets them, chords from the bottom up, so
+    // "after a played note" always means the same note.
+    reached.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then(a.1.total_cmp(&b.1))
+            .then((a.2, a.3).cmp(&(b.2, b.3)))
+    });
+    let (_, rng) =
+        probability_rng.get_or_insert_with(|| (seed, sequencer_rng.stream(PROBABILITY_STREAM)));
+
+    for (playhead_entity, _, _, collider_entity, pass) in reached {
+        let Ok((_, note, _, mut collider)) = collider_query.get_mut(collider_entity) else {
+            continue;
+        };
+        let previous_played = last_played.get(&playhead_entity).copied().unwrap_or(false);
+        collider.played =
+            note.condition.holds(pass, previous_played) && rng.gen::<f32>() < note.probability;
+        last_played.insert(playhead_entity, collider.played);
+        if collider.played {
+            midi_out_note_on.send(NoteOnEvent(collider_entity, 0));
+        }
+    }
 }
@@ -0,0 +1,338 @@
+use std::{fs, path::PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    automaton::{automaton_notes, Automaton, AutomatonCell},
+    euclidean::EuclideanNote,
+    keymap::{Action, Keymap},
+    layer::layer_of,
+    mouse_input::Selected,
+    note::{spawn_note, Note, Ratchet, TrigCondition, DEFAULT_VELOCITY},
+    playhead::{spawn_playhead, LoopRegion, NoteOffEvent, Playhead, PlayheadDirection},
+    random::{Generated, SequencerRng},
+    sequence::Canvas,
+};
+
+pub struct ProjectPlugin;
+
+impl Plugin for ProjectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProjectSettings>()
+            .add_event::<SaveProject>()
+            .add_event::<LoadProject>()
+            .add_system(project_shortcuts)
+            .add_system(save_project.after(project_shortcuts))
+            // The automaton picks up the loaded cells the next frame, once they exist.
+            .add_system(load_project.after(project_shortcuts).after(automaton_notes));
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct ProjectSettings {
+    pub path: PathBuf,
+}
+
+impl Default for ProjectSettings {
+    fn default() -> Self {
+        ProjectSettings {
+            path: PathBuf::from("project.ron"),
+        }
+    }
+}
+
+pub struct SaveProject;
+
+pub struct LoadProject;
+
+/// What a project file holds.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Project {
+    /// Seed everything random was generated from.
+    pub seed: u64,
+    pub notes: Vec<ProjectNote>,
+    pub playheads: Vec<ProjectPlayhead>,
+    /// Rule, seed and cells of the automaton whose live cells are notes.
+    pub automaton: Automaton,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProjectNote {
+    /// Centre on the canvas.
+    pub position: (f32, f32),
+    pub size: (f32, f32),
+    pub layer: usize,
+    #[serde(default = "default_velocity")]
+    pub velocity: u8,
+    #[serde(default)]
+    pub channel: u8,
+    #[serde(default = "default_probability")]
+    pub probability: f32,
+    #[serde(default)]
+    pub condition: TrigCondition,
+    #[serde(default)]
+    pub ratchet: Ratchet,
+    /// Generated at random, and so replaced when regenerating.
+    pub generated: bool,
+    #[serde(default)]
+    pub generator: Option<Generator>,
+}
+
+/// The generator that placed a note, which replaces it when run again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Generator {
+    Euclidean,
+    /// The automaton cell at these coordinates.
+    Automaton(usize, usize),
+}
+
+impl Generator {
+    pub fn of(euclidean: Option<&EuclideanNote>, cell: Option<&AutomatonCell>) -> Option<Self> {
+        match (euclidean, cell) {
+            (Some(_), _) => Some(Generator::Euclidean),
+            (_, Some(AutomatonCell(x, y))) => Some(Generator::Automaton(*x, *y)),
+            _ => None,
+        }
+    }
+}
+
+fn default_velocity() -> u8 {
+    DEFAULT_VELOCITY
+}
+
+fn default_probability() -> f32 {
+    Note::default().probability
+}
+
+impl ProjectNote {
+    pub fn new(
+        note: &Note,
+        transform: &Transform,
+        generated: bool,
+        generator: Option<Generator>,
+    ) -> Self {
+        ProjectNote {
+            position: (transform.translation.x, transform.translation.y),
+            size: (transform.scale.x, transform.scale.y),
+            layer: layer_of(transform),
+            velocity: note.velocity,
+            channel: note.channel,
+            probability: note.probability,
+            condition: note.condition,
+            ratchet: note.ratchet,
+            generated,
+            generator,
+        }
+    }
+
+    /// Whether the note starts on the canvas, where playheads reach it.
+    pub fn on_canvas(&self, canvas: &Canvas) -> bool {
+        let left = self.position.0 - self.size.0 / 2.;
+        (0.0..canvas.width).contains(&left)
+    }
+
+    pub fn spawn(&self, commands: &mut Commands) -> Entity {
+        let translation = Vec3::new(self.position.0, self.position.1, self.layer as f32);
+        let size = Vec2::new(self.size.0, self.size.1);
+        let entity = spawn_note(commands, translation, size);
+        commands.entity(entity).insert(Note {
+            velocity: self.velocity,
+            channel: self.channel,
+            probability: self.probability,
+            condition: self.condition,
+            ratchet: self.ratchet,
+            ..default()
+        });
+        if self.generated {
+            commands.entity(entity).insert(Generated);
+        }
+        match self.generator {
+            Some(Generator::Euclidean) => {
+                commands.entity(entity).insert(EuclideanNote);
+            }
+            Some(Generator::Automaton(x, y)) => {
+                commands.entity(entity).insert(AutomatonCell(x, y));
+            }
+            None => {}
+        }
+        entity
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProjectPlayhead {
+    pub x: f32,
+    pub layer: usize,
+    /// Loops played per loop at the tempo.
+    pub rate: f32,
+    pub direction: PlayheadDirection,
+    #[serde(default)]
+    pub start: f32,
+    /// RGBA.
+    #[serde(default = "default_playhead_color")]
+    pub color: [f32; 4],
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub solo: bool,
+    #[serde(default)]
+    pub region: Option<LoopRegion>,
+}
+
+fn default_playhead_color() -> [f32; 4] {
+    Playhead::default().color.as_rgba_f32()
+}
+
+impl ProjectPlayhead {
+    pub fn new(transform: &Transform, playhead: &Playhead) -> Self {
+        ProjectPlayhead {
+            x: transform.translation.x,
+            layer: layer_of(transform),
+            rate: playhead.rate,
+            direction: playhead.direction,
+            start: playhead.start,
+            color: playhead.color.as_rgba_f32(),
+            muted: playhead.muted,
+            solo: playhead.solo,
+            region: playhead.region,
+        }
+    }
+
+    pub fn spawn(&self, commands: &mut Commands, canvas: &Canvas) -> Entity {
+        spawn_playhead(
+            commands,
+            canvas,
+            Vec3::new(self.x, 0., self.layer as f32),
+            Playhead {
+                rate: self.rate,
+                direction: self.direction,
+                current_direction: self.direction.initial(),
+                start: self.start,
+                color: Color::from(self.color),
+                muted: self.muted,
+                solo: self.solo,
+                region: self.region,
+                passes: 0,
+            },
+        )
+    }
+}
+
+/// Ctrl+S saves, Ctrl+O opens, by default.
+fn project_shortcuts(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut save_events: EventWriter<SaveProject>,
+    mut load_events: EventWriter<LoadProject>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    if keymap.just_pressed(Action::Save, &keyboard_input) {
+        save_events.send(SaveProject);
+    }
+    if keymap.just_pressed(Action::Open, &keyboard_input) {
+        load_events.send(LoadProject);
+    }
+}
+
+/// What tells which generator, if any, placed a note.
+pub type GeneratorMarkers = (
+    Option<&'static Generated>,
+    Option<&'static EuclideanNote>,
+    Option<&'static AutomatonCell>,
+);
+
+fn save_project(
+    mut save_events: EventReader<SaveProject>,
+    settings: Res<ProjectSettings>,
+    sequencer_rng: Res<SequencerRng>,
+    automaton: Res<Automaton>,
+    note_query: Query<(&Note, &Transform, GeneratorMarkers)>,
+    playhead_query: Query<(&Transform, &Playhead)>,
+) {
+    if save_events.iter().count() == 0 {
+        return;
+    }
+
+    let project = Project {
+        seed: sequencer_rng.seed(),
+        notes: note_query
+            .iter()
+            .map(|(note, transform, (generated, euclidean, cell))| {
+                let generator = Generator::of(euclidean, cell);
+                ProjectNote::new(note, transform, generated.is_some(), generator)
+            })
+            .collect(),
+        playheads: playhead_query
+            .iter()
+            .map(|(transform, playhead)| ProjectPlayhead::new(transform, playhead))
+            .collect(),
+        automaton: automaton.clone(),
+    };
+
+    let result = ron::ser::to_string_pretty(&project, ron::ser::PrettyConfig::default())
+        .map_err(|error| error.to_string())
+        .and_then(|text| fs::write(&settings.path, text).map_err(|error| error.to_string()));
+
+    match result {
+        Ok(()) => info!("saved project to {:?}", settings.path),
+        Err(error) => error!("could not save project to {:?}: {}", settings.path, error),
+    }
+}
+
+/// Replaces every note and playhead with the project's and restores its seed and
+/// automaton.
+#[allow(clippy::too_many_arguments)]
+fn load_project(
+    mut commands: Commands,
+    mut load_events: EventReader<LoadProject>,
+    settings: Res<ProjectSettings>,
+    canvas: Res<Canvas>,
+    mut sequencer_rng: ResMut<SequencerRng>,
+    mut automaton: ResMut<Automaton>,
+    mut selected: ResMut<Selected>,
+    note_query: Query<Entity, (With<Note>, With<Sprite>)>,
+    playhead_query: Query<Entity, With<Playhead>>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+) {
+    if load_events.iter().count() == 0 {
+        return;
+    }
+
+    let project = fs::read_to_string(&settings.path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| ron::from_str::<Project>(&text).map_err(|error| error.to_string()));
+    let project = match project {
+        Ok(project) => project,
+        Err(error) => {
+            error!("could not load project {:?}: {}", settings.path, error);
+            return;
+        }
+    };
+
+    for entity in note_query.iter() {
+        midi_out_note_off.send(NoteOffEvent(entity));
+        commands.entity(entity).despawn_recursive();
+    }
+    for entity in playhead_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    selected.entities.clear();
+    sequencer_rng.reseed(project.seed);
+    // Before the notes, so the cells they stand for are alive.
+    automaton.restore(project.automaton);
+
+    for note in project.notes.iter() {
+        note.spawn(&mut commands);
+    }
+    for playhead in project.playheads.iter() {
+        playhead.spawn(&mut commands, &canvas);
+    }
+
+    info!("loaded project {:?}", settings.path);
+}
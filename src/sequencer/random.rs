@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+use super::{note::Note, playhead::NoteOffEvent};
+
+pub struct RandomPlugin;
+
+impl Plugin for RandomPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SequencerRng>()
+            .add_event::<Regenerate>()
+            .add_system(
+                clear_generated
+                    .in_base_set(CoreSet::PreUpdate)
+                    .run_if(on_regenerate()),
+            );
+    }
+}
+
+/// The one source of randomness for everything generative, so the same seed always
+/// builds the same scene.
+#[derive(Resource, Debug)]
+pub struct SequencerRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Default for SequencerRng {
+    /// Only the first seed comes from entropy.
+    fn default() -> Self {
+        SequencerRng::new(rand::random())
+    }
+}
+
+impl SequencerRng {
+    pub fn new(seed: u64) -> Self {
+        SequencerRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// A separate generator from the same seed, told apart by `stream`, for randomness
+    /// that mustn't change what the seed builds.
+    pub fn stream(&self, stream: u64) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ stream)
+    }
+
+    /// Restarts the random sequence from `seed`.
+    pub fn reseed(&mut self, seed: u64) {
+        *self = SequencerRng::new(seed);
+    }
+}
+
+impl RngCore for SequencerRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+/// Replaces everything generated at random with what the current seed generates.
+pub struct Regenerate;
+
+/// Marks entities generated at random, which regenerating replaces.
+#[derive(Component)]
+pub struct Generated;
+
+pub fn on_regenerate() -> impl FnMut(EventReader<Regenerate>) -> bool + Clone {
+    on_event::<Regenerate>()
+}
+
+/// Rewinds the random sequence and removes the old scene. The generators then run again
+/// after it, reading the same event in the same frame, so senders in the update never
+/// race them.
+pub fn clear_generated(
+    mut commands: Commands,
+    mut sequencer_rng: ResMut<SequencerRng>,
+    generated_query: Query<(Entity, Option<&Note>), With<Generated>>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+) {
+    let seed = sequencer_rng.seed();
+    sequencer_rng.reseed(seed);
+
+    for (entity, note) in generated_query.iter() {
+        if note.is_some() {
+            midi_out_note_off.send(NoteOffEvent(entity));
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// The seed, with buttons to regenerate from it or from a new one.
+pub fn seed_editor(
+    ui: &mut egui::Ui,
+    sequencer_rng: &mut SequencerRng,
+    regenerate: &mut EventWriter<Regenerate>,
+) {
+    let mut seed = sequencer_rng.seed();
+    ui.horizontal(|ui| {
+        ui.label("Seed");
+        ui.add(egui::DragValue::new(&mut seed));
+    });
+
+    ui.horizontal(|ui| {
+        if ui.button("Regenerate").clicked() {
+            regenerate.send(Regenerate);
+        }
+        if ui
+            .button("Reroll")
+            .on_hover_text("Regenerate from a new seed")
+            .clicked()
+        {
+            seed = sequencer_rng.next_u64();
+            regenerate.send(Regenerate);
+        }
+    });
+
+    if seed != sequencer_rng.seed() {
+        sequencer_rng.reseed(seed);
+    }
+}
@@ -0,0 +1,222 @@
+use bevy_egui::egui;
+
+pub const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Note name with octave, middle C (60) being C4.
+pub fn note_name(pitch: u8) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[pitch as usize % 12],
+        pitch as i32 / 12 - 1
+    )
+}
+
+pub fn is_black_key(pitch: u8) -> bool {
+    matches!(pitch % 12, 1 | 3 | 6 | 8 | 10)
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Scale {
+    #[default]
+    Chromatic,
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    HarmonicMinor,
+    /// Semitones above the root, within one octave.
+    Custom(Vec<u8>),
+}
+
+impl Scale {
+    /// Every scale except `Custom`, in menu order.
+    pub const PRESETS: [Scale; 11] = [
+        Scale::Chromatic,
+        Scale::Major,
+        Scale::Minor,
+        Scale::Dorian,
+        Scale::Phrygian,
+        Scale::Lydian,
+        Scale::Mixolydian,
+        Scale::Locrian,
+        Scale::MajorPentatonic,
+        Scale::MinorPentatonic,
+        Scale::HarmonicMinor,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scale::Chromatic => "Chromatic",
+            Scale::Major => "Major",
+            Scale::Minor => "Minor",
+            Scale::Dorian => "Dorian",
+            Scale::Phrygian => "Phrygian",
+            Scale::Lydian => "Lydian",
+            Scale::Mixolydian => "Mixolydian",
+            Scale::Locrian => "Locrian",
+            Scale::MajorPentatonic => "Major pentatonic",
+            Scale::MinorPentatonic => "Minor pentatonic",
+            Scale::HarmonicMinor => "Harmonic minor",
+            Scale::Custom(_) => "Custom",
+        }
+    }
+
+    pub fn intervals(&self) -> &[u8] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::Custom(intervals) => intervals,
+        }
+    }
+
+    /// Position of `pitch` in the scale built on `root` (a pitch class), if it's in key.
+    pub fn degree(&self, pitch: u8, root: u8) -> Option<usize> {
+        let interval = (pitch + 12 - root % 12) % 12;
+        self.intervals()
+            .iter()
+            .position(|scale_interval| scale_interval % 12 == interval)
+    }
+
+    /// The nearest in-key pitch, preferring the lower one on a tie.
+    pub fn quantize(&self, pitch: u8, root: u8) -> u8 {
+        if self.intervals().is_empty() {
+            return pitch;
+        }
+
+        (0..12)
+            .flat_map(|distance| [pitch.checked_sub(distance), pitch.checked_add(distance)])
+            .flatten()
+            .find(|candidate| *candidate <= 127 && self.degree(*candidate, root).is_some())
+            .unwrap_or(pitch)
+    }
+}
+
+/// Parses space or comma separated semitones, e.g. "0 2 3 7 9".
+pub fn parse_intervals(text: &str) -> Option<Vec<u8>> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<u8>().ok().filter(|interval| *interval < 12))
+        .collect::<Option<Vec<u8>>>()
+        .filter(|intervals| !intervals.is_empty())
+}
+
+/// Radio buttons for the scale and root, plus a text field for custom intervals.
+pub fn scale_picker(ui: &mut egui::Ui, scale: &mut Scale, root: &mut u8, custom_text: &mut String) {
+    ui.horizontal_wrapped(|ui| {
+        for (pitch_class, name) in NOTE_NAMES.iter().enumerate() {
+            ui.radio_value(root, pitch_class as u8, *name);
+        }
+    });
+    ui.separator();
+
+    for preset in Scale::PRESETS {
+        let name = preset.name();
+        ui.radio_value(scale, preset, name);
+    }
+
+    ui.horizontal(|ui| {
+        let is_custom = matches!(scale, Scale::Custom(_));
+        if ui.radio(is_custom, "Custom").clicked() || is_custom {
+            if let Some(intervals) = parse_intervals(custom_text) {
+                if *scale != Scale::Custom(intervals.clone()) {
+                    *scale = Scale::Custom(intervals);
+                }
+            }
+        }
+        ui.text_edit_singleline(custom_text)
+            .on_hover_text("Semitones above the root, e.g. 0 2 3 7 9");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_degrees_from_the_root() {
+        let cases = [
+            (Scale::Major, 0, 60, Some(0)),
+            (Scale::Major, 0, 62, Some(1)),
+            (Scale::Major, 0, 71, Some(6)),
+            (Scale::Major, 0, 61, None),
+            (Scale::Major, 7, 67, Some(0)),
+            (Scale::Major, 7, 66, Some(6)),
+            (Scale::Major, 7, 65, None),
+            (Scale::Major, 14, 62, Some(0)),
+            (Scale::Chromatic, 5, 0, Some(7)),
+        ];
+        for (scale, root, pitch, degree) in cases {
+            assert_eq!(
+                scale.degree(pitch, root),
+                degree,
+                "{:?} {} {}",
+                scale,
+                root,
+                pitch
+            );
+        }
+    }
+
+    #[test]
+    fn quantizes_to_the_nearest_pitch_in_key() {
+        let cases = [
+            // In key already.
+            (Scale::Major, 0, 64, 64),
+            (Scale::Chromatic, 3, 61, 61),
+            // Ties go down.
+            (Scale::Major, 0, 61, 60),
+            (Scale::Major, 0, 66, 65),
+            (Scale::Major, 2, 60, 59),
+            (Scale::MinorPentatonic, 9, 61, 60),
+            // Nearest, whichever way.
+            (Scale::Custom(vec![0, 7]), 0, 64, 67),
+            (Scale::Custom(vec![0, 7]), 0, 62, 60),
+            (Scale::Custom(vec![4]), 0, 60, 64),
+            // Never past the MIDI range.
+            (Scale::Major, 2, 0, 1),
+            (Scale::Major, 9, 127, 126),
+            (Scale::Major, 0, 127, 127),
+            (Scale::Custom(Vec::new()), 0, 61, 61),
+        ];
+        for (scale, root, pitch, expected) in cases {
+            assert_eq!(
+                scale.quantize(pitch, root),
+                expected,
+                "{:?} {} {}",
+                scale,
+                root,
+                pitch
+            );
+        }
+    }
+
+    #[test]
+    fn parses_custom_intervals() {
+        assert_eq!(parse_intervals("0 2 3 7 9"), Some(vec![0, 2, 3, 7, 9]));
+        assert_eq!(parse_intervals("0,4, 7"), Some(vec![0, 4, 7]));
+        assert_eq!(parse_intervals(" 11 "), Some(vec![11]));
+    }
+
+    #[test]
+    fn rejects_bad_intervals() {
+        for text in ["", " , ", "0 12", "0 x", "-1", "0 2.5"] {
+            assert_eq!(parse_intervals(text), None, "{:?}", text);
+        }
+    }
+}
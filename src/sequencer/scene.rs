@@ -0,0 +1,65 @@
+use std::{ops::RangeInclusive, path::PathBuf};
+
+use bevy::prelude::*;
+
+use super::project::{LoadProject, ProjectSettings};
+
+pub struct ScenePlugin;
+
+impl Plugin for ScenePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StartupScene>()
+            .init_resource::<RandomScene>()
+            .add_startup_system(load_startup_project);
+    }
+}
+
+/// What the sequencer starts out with.
+#[derive(Resource, Clone, Debug, Default)]
+pub enum StartupScene {
+    Empty,
+    /// Generated from the `RandomScene` settings.
+    #[default]
+    Random,
+    Project(PathBuf),
+}
+
+/// What generating a random scene spawns.
+#[derive(Resource, Clone, Debug)]
+pub struct RandomScene {
+    /// One playhead per layer, starting at layer 0.
+    pub playheads: usize,
+    /// Notes per layer.
+    pub notes: usize,
+    /// Playhead rates are picked from this range, 1 crossing the canvas once per loop.
+    pub rate: RangeInclusive<f32>,
+    /// Size of new notes, random or placed by hand.
+    pub note_size: Vec2,
+}
+
+impl Default for RandomScene {
+    fn default() -> Self {
+        RandomScene {
+            playheads: 2,
+            notes: 3,
+            rate: 0.5..=2.0,
+            note_size: Vec2::new(120., 20.),
+        }
+    }
+}
+
+/// Run condition for the random scene's generators at startup.
+pub fn random_startup(startup_scene: Res<StartupScene>) -> bool {
+    matches!(*startup_scene, StartupScene::Random)
+}
+
+fn load_startup_project(
+    startup_scene: Res<StartupScene>,
+    mut project_settings: ResMut<ProjectSettings>,
+    mut load_events: EventWriter<LoadProject>,
+) {
+    if let StartupScene::Project(path) = &*startup_scene {
+        project_settings.path = path.clone();
+        load_events.send(LoadProject);
+    }
+}
@@ -1,10 +1,61 @@
 use bevy::prelude::*;
 
+use super::{layer::Layer, playhead::Rewind, scale::Scale};
+
 pub struct SequencePlugin;
 
 impl Plugin for SequencePlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<GlobalSequencerSettings>();
+        app.init_resource::<GlobalSequencerSettings>()
+            .init_resource::<Canvas>()
+            .init_resource::<Transport>()
+            .add_startup_system(spawn_canvas_background)
+            .add_system(transport_clock)
+            .add_system(canvas_background);
+    }
+}
+
+/// The note field, in world units. The window is only a view onto it.
+#[derive(Resource, Debug)]
+pub struct Canvas {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Canvas {
+            width: 2560.,
+            height: 1440.,
+        }
+    }
+}
+
+impl Canvas {
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width, self.height)
+    }
+}
+
+#[derive(Component)]
+struct CanvasBackground;
+
+#[derive(Resource, Debug)]
+pub struct Transport {
+    pub playing: bool,
+    /// Master tempo in beats per minute. Playheads move at their rates of it.
+    pub tempo: f32,
+    /// Beats played since the last stop.
+    pub beats: f64,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport {
+            playing: true,
+            tempo: 120.,
+            beats: 0.,
+        }
     }
 }
 
@@ -12,6 +63,31 @@ impl Plugin for SequencePlugin {
 pub struct GlobalSequencerSettings {
     pub pitch_min: u8,
     pub pitch_max: u8,
+    /// A playhead sweeping the whole note field plays this many bars.
+    pub bars_per_loop: u32,
+    pub beats_per_bar: u32,
+    pub scale: Scale,
+    /// Pitch class the scale is built on, 0 being C.
+    pub root: u8,
+}
+
+impl GlobalSequencerSettings {
+    pub fn beats_per_loop(&self) -> u32 {
+        self.bars_per_loop * self.beats_per_bar
+    }
+
+    /// The scale and root notes on `layer` are quantized to.
+    pub fn scale_for<'a>(&'a self, layer: &'a Layer) -> (&'a Scale, u8) {
+        (
+            layer.scale.as_ref().unwrap_or(&self.scale),
+            layer.root.unwrap_or(self.root),
+        )
+    }
+
+    /// Width of one beat when the loop spans `loop_width`.
+    pub fn beat_width(&self, loop_width: f32) -> f32 {
+        loop_width / self.beats_per_loop() as f32
+    }
 }
 
 impl Default for GlobalSequencerSettings {
@@ -19,6 +95,47 @@ impl Default for GlobalSequencerSettings {
         GlobalSequencerSettings {
             pitch_min: 40,
             pitch_max: 110,
+            bars_per_loop: 4,
+            beats_per_bar: 4,
+            scale: Scale::default(),
+            root: 0,
         }
     }
 }
+
+/// Counts beats while playing, from 0 again after Stop.
+pub fn transport_clock(
+    time: Res<Time>,
+    mut rewinds: EventReader<Rewind>,
+    mut transport: ResMut<Transport>,
+) {
+    if rewinds.iter().count() > 0 {
+        transport.beats = 0.;
+    }
+    if transport.playing {
+        transport.beats += time.delta_seconds_f64() * transport.tempo as f64 / 60.;
+    }
+}
+
+fn spawn_canvas_background(mut commands: Commands) {
+    commands
+        .spawn(SpriteBundle {
+            transform: Transform::from_xyz(0., 0., -1.),
+            sprite: Sprite {
+                color: Color::rgb(0.1, 0.1, 0.12),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(CanvasBackground);
+}
+
+fn canvas_background(
+    canvas: Res<Canvas>,
+    mut background_query: Query<&mut Transform, With<CanvasBackground>>,
+) {
+    for mut transform in background_query.iter_mut() {
+        transform.translation = (canvas.size() / 2.).extend(transform.translation.z);
+        transform.scale = canvas.size().extend(1.);
+    }
+}
@@ -0,0 +1,414 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use super::{
+    keymap::{Action, Keymap},
+    note::Note,
+    playhead::{NoteOffEvent, NoteOnEvent},
+    scale::note_name,
+    sequence::{transport_clock, GlobalSequencerSettings, Transport},
+    tuning::{LoadedTuning, TuningSettings},
+};
+
+const GRID_SIZE_X: usize = 16;
+const GRID_SIZE_Y: usize = 8;
+const MAX_GRID_SIZE: usize = 64;
+const CELL_SIZE: f32 = 18.0;
+/// A sixteenth note.
+const DEFAULT_STEP_BEATS: f32 = 0.25;
+const MIN_STEP_BEATS: f32 = 1. / 32.;
+const MAX_STEP_BEATS: f32 = 4.;
+
+pub struct StepGridPlugin;
+
+impl Plugin for StepGridPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Cartesian>()
+            .add_system(step_voices.in_base_set(CoreSet::PreUpdate))
+            .add_system(tick.after(transport_clock))
+            .add_system(step_grid_window);
+    }
+}
+
+/// A grid of cells toggled on and off, with cursors walking over it one step at a time
+/// on the transport's beat. Columns are steps, rows climb the global scale from
+/// `base_pitch`.
+#[derive(Resource, Debug)]
+pub struct Cartesian {
+    pub running: bool,
+    /// Columns and rows.
+    pub size: (usize, usize),
+    /// Row-major, the bottom row first.
+    cells: Vec<bool>,
+    pub cursors: Vec<StepCursor>,
+    pub base_pitch: u8,
+    /// Length of a step, in beats.
+    pub step_beats: f32,
+}
+
+impl Default for Cartesian {
+    fn default() -> Self {
+        Cartesian {
+            running: false,
+            size: (GRID_SIZE_X, GRID_SIZE_Y),
+            cells: vec![false; GRID_SIZE_X * GRID_SIZE_Y],
+            cursors: vec![StepCursor::default()],
+            base_pitch: 60,
+            step_beats: DEFAULT_STEP_BEATS,
+        }
+    }
+}
+
+impl Cartesian {
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.cells[y * self.size.0 + x]
+    }
+
+    pub fn toggle(&mut self, x: usize, y: usize) {
+        let index = y * self.size.0 + x;
+        self.cells[index] = !self.cells[index];
+    }
+
+    /// Keeps the cells that still fit and wraps the cursors back onto the grid.
+    pub fn resize(&mut self, columns: usize, rows: usize) {
+        let mut cells = vec![false; columns * rows];
+        for y in 0..rows.min(self.size.1) {
+            for x in 0..columns.min(self.size.0) {
+                cells[y * columns + x] = self.get(x, y);
+            }
+        }
+        self.cells = cells;
+        self.size = (columns, rows);
+
+        for cursor in self.cursors.iter_mut() {
+            cursor.position = wrap(cursor.position, self.size);
+        }
+    }
+
+    /// The in-key pitch `row` rows above `base_pitch`.
+    pub fn row_pitch(&self, row: usize, settings: &GlobalSequencerSettings) -> Option<u8> {
+        (self.base_pitch..=127)
+            .filter(|pitch| settings.scale.degree(*pitch, settings.root).is_some())
+            .nth(row)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StepDirection {
+    #[default]
+    Right,
+    Left,
+    Up,
+    Down,
+    UpRight,
+    UpLeft,
+    DownRight,
+    DownLeft,
+}
+
+impl StepDirection {
+    pub const ALL: [StepDirection; 8] = [
+        StepDirection::Right,
+        StepDirection::Left,
+        StepDirection::Up,
+        StepDirection::Down,
+        StepDirection::UpRight,
+        StepDirection::UpLeft,
+        StepDirection::DownRight,
+        StepDirection::DownLeft,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StepDirection::Right => "Right",
+            StepDirection::Left => "Left",
+            StepDirection::Up => "Up",
+            StepDirection::Down => "Down",
+            StepDirection::UpRight => "Up right",
+            StepDirection::UpLeft => "Up left",
+            StepDirection::DownRight => "Down right",
+            StepDirection::DownLeft => "Down left",
+        }
+    }
+
+    pub fn delta(&self) -> (i32, i32) {
+        match self {
+            StepDirection::Right => (1, 0),
+            StepDirection::Left => (-1, 0),
+            StepDirection::Up => (0, 1),
+            StepDirection::Down => (0, -1),
+            StepDirection::UpRight => (1, 1),
+            StepDirection::UpLeft => (-1, 1),
+            StepDirection::DownRight => (1, -1),
+            StepDirection::DownLeft => (-1, -1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StepCursor {
+    pub position: (i32, i32),
+    pub direction: StepDirection,
+    /// The note entity this cursor plays through.
+    voice: Option<Entity>,
+}
+
+/// Marks the note a step cursor plays, which has no sprite and so stays off the canvas.
+#[derive(Component)]
+struct StepVoice;
+
+fn wrap(position: (i32, i32), size: (usize, usize)) -> (i32, i32) {
+    (
+        position.0.rem_euclid(size.0 as i32),
+        position.1.rem_euclid(size.1 as i32),
+    )
+}
+
+/// Gives every cursor a note to play through and removes the notes of removed cursors.
+fn step_voices(
+    mut commands: Commands,
+    mut cartesian: ResMut<Cartesian>,
+    voice_query: Query<Entity, With<StepVoice>>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+) {
+    if !cartesian.is_changed() {
+        return;
+    }
+
+    // Only a cursor's voice changes here, which nothing needs to react to.
+    for cursor in cartesian.bypass_change_detection().cursors.iter_mut() {
+        if cursor.voice.is_none() {
+            let voice = commands.spawn(Note::default()).insert(StepVoice).id();
+            cursor.voice = Some(voice);
+        }
+    }
+
+    for entity in voice_query.iter() {
+        let in_use = cartesian
+            .cursors
+            .iter()
+            .any(|cursor| cursor.voice == Some(entity));
+        if !in_use || !cartesian.running {
+            midi_out_note_off.send(NoteOffEvent(entity));
+        }
+        if !in_use {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// At every step of the transport, plays the cell under every cursor and then moves it
+/// on, so the cells cursors start on play first. Pausing releases what they play.
+#[allow(clippy::too_many_arguments)]
+fn tick(
+    transport: Res<Transport>,
+    mut cartesian: ResMut<Cartesian>,
+    mut note_query: Query<&mut Note, With<StepVoice>>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+    tuning_settings: Res<TuningSettings>,
+    loaded_tuning: Res<LoadedTuning>,
+    mut midi_out_note_on: EventWriter<NoteOnEvent>,
+    mut midi_out_note_off: EventWriter<NoteOffEvent>,
+    mut last_step: Local<Option<u64>>,
+    mut was_playing: Local<bool>,
+) {
+    if !cartesian.running || !transport.playing {
+        if *was_playing {
+            for voice in cartesian.cursors.iter().filter_map(|cursor| cursor.voice) {
+                midi_out_note_off.send(NoteOffEvent(voice));
+            }
+        }
+        *was_playing = false;
+        return;
+    }
+    *was_playing = true;
+
+    let step = (transport.beats / cartesian.step_beats as f64).floor() as u64;
+    if *last_step == Some(step) {
+        return;
+    }
+    *last_step = Some(step);
+
+    let size = cartesian.size;
+    let mut triggered = Vec::new();
+
+    for cursor in cartesian.cursors.iter_mut() {
+        if let Some(voice) = cursor.voice {
+            midi_out_note_off.send(NoteOffEvent(voice));
+            triggered.push((voice, cursor.position));
+        }
+
+        let (dx, dy) = cursor.direction.delta();
+        cursor.position = wrap((cursor.position.0 + dx, cursor.position.1 + dy), size);
+    }
+
+    for (voice, (x, y)) in triggered {
+        if !cartesian.get(x as usize, y as usize) {
+            continue;
+        }
+        let Some(key) = cartesian.row_pitch(y as usize, &sequencer_settings) else {
+            continue;
+        };
+
+        if let Ok(mut note) = note_query.get_mut(voice) {
+            (note.pitch, note.bend) = loaded_tuning
+                .0
+                .as_ref()
+                .and_then(|tuning| tuning.midi_note(key, tuning_settings.pitch_bend_range))
+                .unwrap_or((key, 0));
+            midi_out_note_on.send(NoteOnEvent(voice, 0));
+        }
+    }
+}
+
+/// Toggled with G by default. Clicking a cell switches it on or off.
+fn step_grid_window(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut open: Local<bool>,
+    mut cartesian: ResMut<Cartesian>,
+    sequencer_settings: Res<GlobalSequencerSettings>,
+) {
+    let ctx = contexts.ctx_mut();
+    if !ctx.wants_keyboard_input() && keymap.just_pressed(Action::ToggleStepGrid, &keyboard_input) {
+        *open = !*open;
+    }
+
+    let mut window_open = *open;
+    egui::Window::new("Step grid")
+        .open(&mut window_open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            let mut running = cartesian.running;
+            ui.checkbox(&mut running, "Running");
+            if running != cartesian.running {
+                cartesian.running = running;
+            }
+
+            let (mut columns, mut rows) = cartesian.size;
+            let mut base_pitch = cartesian.base_pitch;
+            let mut step_beats = cartesian.step_beats;
+            egui::Grid::new("step_grid_settings").show(ui, |ui| {
+                ui.label("Size");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut columns).clamp_range(1..=MAX_GRID_SIZE));
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut rows).clamp_range(1..=MAX_GRID_SIZE));
+                });
+                ui.end_row();
+                ui.label("Lowest pitch");
+                ui.add(
+                    egui::DragValue::new(&mut base_pitch)
+                        .clamp_range(0..=127)
+                        .custom_formatter(|pitch, _| note_name(pitch as u8)),
+                );
+                ui.end_row();
+                ui.label("Step length");
+                ui.add(
+                    egui::Slider::new(&mut step_beats, MIN_STEP_BEATS..=MAX_STEP_BEATS)
+                        .logarithmic(true)
+                        .suffix(" beats"),
+                );
+                ui.end_row();
+            });
+
+            if (columns, rows) != cartesian.size {
+                cartesian.resize(columns, rows);
+            }
+            if base_pitch != cartesian.base_pitch {
+                cartesian.base_pitch = base_pitch;
+            }
+            if step_beats != cartesian.step_beats {
+                cartesian.step_beats = step_beats;
+            }
+            ui.separator();
+
+            let (columns, rows) = cartesian.size;
+            let (rect, response) = ui.allocate_exact_size(
+                egui::vec2(columns as f32, rows as f32) * CELL_SIZE,
+                egui::Sense::click(),
+            );
+            // Row 0 is drawn at the bottom so pitch rises upwards, as on the canvas.
+            let cell_rect = |x: usize, y: usize| {
+                egui::Rect::from_min_size(
+                    rect.min + egui::vec2(x as f32, (rows - 1 - y) as f32) * CELL_SIZE,
+                    egui::Vec2::splat(CELL_SIZE),
+                )
+                .shrink(1.)
+            };
+
+            if response.clicked() {
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    let offset = (pointer - rect.min) / CELL_SIZE;
+                    let x = (offset.x as usize).min(columns - 1);
+                    let y = rows - 1 - (offset.y as usize).min(rows - 1);
+                    cartesian.toggle(x, y);
+                }
+            }
+
+            let painter = ui.painter();
+            for y in 0..rows {
+                for x in 0..columns {
+                    let fill = if cartesian.get(x, y) {
+                        egui::Color32::from_rgb(0, 200, 0)
+                    } else {
+                        egui::Color32::from_gray(40)
+                    };
+                    painter.rect_filled(cell_rect(x, y), 2., fill);
+                }
+            }
+            for cursor in cartesian.cursors.iter() {
+                let (x, y) = cursor.position;
+                painter.rect_stroke(
+                    cell_rect(x as usize, y as usize),
+                    2.,
+                    egui::Stroke::new(2., egui::Color32::RED),
+                );
+            }
+
+            response.on_hover_text(match cartesian.row_pitch(0, &sequencer_settings) {
+                Some(pitch) => format!("Bottom row plays {}", note_name(pitch)),
+                None => "No pitch in key above the lowest pitch".to_string(),
+            });
+            ui.separator();
+
+            let mut removed = None;
+            let mut cursors = cartesian.cursors.clone();
+            for (index, cursor) in cursors.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Cursor {}", index + 1));
+                    egui::ComboBox::from_id_source(("step_cursor_direction", index))
+                        .selected_text(cursor.direction.name())
+                        .show_ui(ui, |ui| {
+                            for direction in StepDirection::ALL {
+                                ui.selectable_value(
+                                    &mut cursor.direction,
+                                    direction,
+                                    direction.name(),
+                                );
+                            }
+                        });
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            }
+            if let Some(index) = removed {
+                cursors.remove(index);
+            }
+            if ui.button("Add cursor").clicked() {
+                cursors.push(StepCursor::default());
+            }
+
+            let cursors_changed = cursors.len() != cartesian.cursors.len()
+                || cursors
+                    .iter()
+                    .zip(cartesian.cursors.iter())
+                    .any(|(new, old)| new.direction != old.direction);
+            if cursors_changed {
+                cartesian.cursors = cursors;
+            }
+        });
+    *open = window_open;
+}
@@ -0,0 +1,447 @@
+use std::{fs, path::PathBuf};
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+pub struct TuningPlugin;
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TuningSettings>()
+            .init_resource::<LoadedTuning>()
+            .add_system(load_tuning);
+    }
+}
+
+/// Where to read the tuning from. Without a Scala file notes are 12-TET.
+#[derive(Resource, Debug)]
+pub struct TuningSettings {
+    pub scl_path: Option<PathBuf>,
+    pub kbm_path: Option<PathBuf>,
+    /// Pitch bend range of the receiving synth, in semitones.
+    pub pitch_bend_range: f32,
+}
+
+impl Default for TuningSettings {
+    fn default() -> Self {
+        TuningSettings {
+            scl_path: None,
+            kbm_path: None,
+            pitch_bend_range: 2.0,
+        }
+    }
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct LoadedTuning(pub Option<Tuning>);
+
+/// A Scala scale: every degree's size in cents above the tonic, the last one being the period.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalaScale {
+    pub description: String,
+    pub cents: Vec<f64>,
+}
+
+/// A Scala keyboard mapping, assigning scale degrees to MIDI keys.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    /// Key on which degree 0 sits.
+    pub middle_key: i32,
+    pub reference_key: i32,
+    pub reference_frequency: f64,
+    /// Scale degree the mapping repeats at.
+    pub octave_degree: i32,
+    /// Scale degree per key within one repetition, `None` for unmapped keys.
+    /// Empty means every key is mapped to the next degree.
+    pub map: Vec<Option<i32>>,
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        KeyboardMapping {
+            middle_key: 60,
+            reference_key: 60,
+            reference_frequency: 261.625_565_300_598_6,
+            octave_degree: 0,
+            map: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+    pub scale: ScalaScale,
+    pub mapping: KeyboardMapping,
+}
+
+/// Non-comment lines of a Scala file, with trailing text after the value dropped.
+fn scala_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .filter(|line| !line.starts_with('!'))
+        .map(|line| line.trim())
+}
+
+fn parse_pitch(line: &str) -> Result<f64, String> {
+    let value = line.split_whitespace().next().unwrap_or_default();
+
+    if value.contains('.') {
+        return value
+            .parse::<f64>()
+            .map_err(|_| format!("invalid cents value: {}", value));
+    }
+
+    let (numerator, denominator) = value.split_once('/').unwrap_or((value, "1"));
+    let numerator = numerator.parse::<f64>();
+    let denominator = denominator.parse::<f64>();
+
+    match (numerator, denominator) {
+        (Ok(numerator), Ok(denominator)) if numerator > 0. && denominator > 0. => {
+            Ok(1200. * (numerator / denominator).log2())
+        }
+        _ => Err(format!("invalid ratio: {}", value)),
+    }
+}
+
+impl ScalaScale {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = scala_lines(text);
+        let description = lines.next().ok_or("missing description")?.to_string();
+        let count = lines
+            .find(|line| !line.is_empty())
+            .and_then(|line| line.split_whitespace().next())
+            .and_then(|count| count.parse::<usize>().ok())
+            .ok_or("missing note count")?;
+
+        let cents = lines
+            .filter(|line| !line.is_empty())
+            .take(count)
+            .map(parse_pitch)
+            .collect::<Result<Vec<f64>, String>>()?;
+
+        if cents.len() != count || count == 0 {
+            return Err(format!("expected {} pitches, found {}", count, cents.len()));
+        }
+
+        Ok(ScalaScale { description, cents })
+    }
+
+    /// Cents above the tonic of `degree`, which may lie outside the first period.
+    pub fn degree_cents(&self, degree: i32) -> f64 {
+        let size = self.cents.len() as i32;
+        let period = self.cents[self.cents.len() - 1];
+        let repetition = degree.div_euclid(size);
+        let step = degree.rem_euclid(size);
+        let within = if step == 0 {
+            0.
+        } else {
+            self.cents[step as usize - 1]
+        };
+
+        repetition as f64 * period + within
+    }
+}
+
+impl KeyboardMapping {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut values = scala_lines(text)
+            .filter(|line| !line.is_empty())
+            .map(|line| line.split_whitespace().next().unwrap_or_default());
+        let mut next = |name: &str| values.next().ok_or(format!("missing {}", name));
+
+        let size = next("map size")?
+            .parse::<usize>()
+            .map_err(|_| "invalid map size")?;
+        // The range of mapped keys is ignored, only keys within the pitch range are played.
+        next("first key")?;
+        next("last key")?;
+        let middle_key = next("middle key")?
+            .parse::<i32>()
+            .map_err(|_| "invalid middle key")?;
+        let reference_key = next("reference key")?
+            .parse::<i32>()
+            .map_err(|_| "invalid reference key")?;
+        let reference_frequency = next("reference frequency")?
+            .parse::<f64>()
+            .map_err(|_| "invalid reference frequency")?;
+        let octave_degree = next("octave degree")?
+            .parse::<i32>()
+            .map_err(|_| "invalid octave degree")?;
+
+        let map = (0..size)
+            .map(|_| match next("map entry") {
+                Ok("x") | Ok("X") | Err(_) => Ok(None),
+                Ok(entry) => entry
+                    .parse::<i32>()
+                    .map(Some)
+                    .map_err(|_| format!("invalid map entry: {}", entry)),
+            })
+            .collect::<Result<Vec<Option<i32>>, String>>()?;
+
+        Ok(KeyboardMapping {
+            middle_key,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            map,
+        })
+    }
+}
+
+impl Tuning {
+    /// Scale degree `key` plays, or `None` if the mapping leaves it unmapped.
+    fn degree(&self, key: i32) -> Option<i32> {
+        let offset = key - self.mapping.middle_key;
+        if self.mapping.map.is_empty() {
+            return Some(offset);
+        }
+
+        let size = self.mapping.map.len() as i32;
+        let octave_degree = if self.mapping.octave_degree > 0 {
+            self.mapping.octave_degree
+        } else {
+            self.scale.cents.len() as i32
+        };
+        let degree = self.mapping.map[offset.rem_euclid(size) as usize]?;
+
+        Some(offset.div_euclid(size) * octave_degree + degree)
+    }
+
+    pub fn frequency(&self, key: u8) -> Option<f64> {
+        let cents = self.scale.degree_cents(self.degree(key as i32)?);
+        let reference_cents = self
+            .scale
+            .degree_cents(self.degree(self.mapping.reference_key).unwrap_or(0));
+
+        Some(self.mapping.reference_frequency * 2f64.powf((cents - reference_cents) / 1200.))
+    }
+
+    /// The nearest MIDI note to `key`'s frequency and the pitch bend reaching it,
+    /// as an offset from centre on the 14-bit scale.
+    pub fn midi_note(&self, key: u8, pitch_bend_range: f32) -> Option<(u8, i16)> {
+        let frequency = self.frequency(key)?;
+        let midi = 69. + 12. * (frequency / 440.).log2();
+        let note = midi.round().clamp(0., 127.);
+        let bend = (midi - note) / pitch_bend_range as f64 * 8192.;
+
+        Some((note as u8, bend.round().clamp(-8192., 8191.) as i16))
+    }
+}
+
+/// Reads the files when their paths change, not when other settings like the pitch bend
+/// range do.
+fn load_tuning(
+    settings: Res<TuningSettings>,
+    mut loaded_tuning: ResMut<LoadedTuning>,
+    mut loaded_paths: Local<Option<(Option<PathBuf>, Option<PathBuf>)>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let paths = (settings.scl_path.clone(), settings.kbm_path.clone());
+    if loaded_paths.as_ref() == Some(&paths) {
+        return;
+    }
+    *loaded_paths = Some(paths);
+
+    let Some(scl_path) = &settings.scl_path else {
+        loaded_tuning.0 = None;
+        return;
+    };
+
+    let tuning = fs::read_to_string(scl_path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| ScalaScale::parse(&text))
+        .and_then(|scale| {
+            let mapping = match &settings.kbm_path {
+                Some(kbm_path) => fs::read_to_string(kbm_path)
+                    .map_err(|error| error.to_string())
+                    .and_then(|text| KeyboardMapping::parse(&text))?,
+                None => KeyboardMapping::default(),
+            };
+
+            Ok(Tuning { scale, mapping })
+        });
+
+    match tuning {
+        Ok(tuning) => {
+            info!("loaded tuning: {}", tuning.scale.description);
+            loaded_tuning.0 = Some(tuning);
+        }
+        Err(error) => {
+            error!("could not load tuning {:?}: {}", scl_path, error);
+            loaded_tuning.0 = None;
+        }
+    }
+}
+
+/// Text fields editing a tuning's file paths, which only take effect on "Load".
+#[derive(Default, Debug)]
+pub struct TuningPaths {
+    scl: String,
+    kbm: String,
+}
+
+pub fn tuning_editor(
+    ui: &mut egui::Ui,
+    settings: &mut TuningSettings,
+    loaded_tuning: &LoadedTuning,
+    paths: &mut TuningPaths,
+) {
+    match &loaded_tuning.0 {
+        Some(tuning) => ui.label(&tuning.scale.description),
+        None => ui.label("12-TET"),
+    };
+
+    egui::Grid::new("tuning_paths").show(ui, |ui| {
+        ui.label("Scala file");
+        ui.text_edit_singleline(&mut paths.scl);
+        ui.end_row();
+        ui.label("Keyboard mapping");
+        ui.text_edit_singleline(&mut paths.kbm);
+        ui.end_row();
+    });
+
+    let mut pitch_bend_range = settings.pitch_bend_range;
+    ui.add(
+        egui::Slider::new(&mut pitch_bend_range, 1.0..=96.0)
+            .text("Pitch bend range")
+            .suffix(" st"),
+    );
+    if pitch_bend_range != settings.pitch_bend_range {
+        settings.pitch_bend_range = pitch_bend_range;
+    }
+
+    ui.horizontal(|ui| {
+        if ui.button("Load").clicked() {
+            let path =
+                |text: &str| Some(PathBuf::from(text.trim())).filter(|_| !text.trim().is_empty());
+            settings.scl_path = path(&paths.scl);
+            settings.kbm_path = path(&paths.kbm);
+        }
+        if ui.button("12-TET").clicked() {
+            settings.scl_path = None;
+            settings.kbm_path = None;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-3;
+
+    #[test]
+    fn parses_ratios_and_cents() {
+        assert!((parse_pitch("2/1").unwrap() - 1200.).abs() < EPSILON);
+        assert!((parse_pitch("3/2").unwrap() - 701.955).abs() < EPSILON);
+        assert!((parse_pitch("5").unwrap() - 2786.314).abs() < EPSILON);
+        assert!((parse_pitch("100.0 a semitone").unwrap() - 100.).abs() < EPSILON);
+        assert!((parse_pitch("-3.5").unwrap() + 3.5).abs() < EPSILON);
+    }
+
+    #[test]
+    fn rejects_bad_pitches() {
+        assert!(parse_pitch("").is_err());
+        assert!(parse_pitch("abc").is_err());
+        assert!(parse_pitch("3/0").is_err());
+        assert!(parse_pitch("0/1").is_err());
+        assert!(parse_pitch("1.2.3").is_err());
+    }
+
+    #[test]
+    fn parses_shipped_19_edo() {
+        let scale = ScalaScale::parse(include_str!("../../assets/tunings/19-edo.scl")).unwrap();
+
+        assert_eq!(scale.description, "19 equal divisions of the octave");
+        assert_eq!(scale.cents.len(), 19);
+        assert!((scale.cents[0] - 1200. / 19.).abs() < EPSILON);
+        assert!((scale.degree_cents(19) - 1200.).abs() < EPSILON);
+        assert!((scale.degree_cents(-1) + 1200. / 19.).abs() < EPSILON);
+    }
+
+    #[test]
+    fn parses_mixed_scale() {
+        let text = "! mixed.scl\n!\nJust and tempered\n 4\n!\n 9/8\n 386.314\n 3/2 fifth\n 2/1\n";
+        let scale = ScalaScale::parse(text).unwrap();
+
+        assert_eq!(scale.description, "Just and tempered");
+        let expected = [203.910, 386.314, 701.955, 1200.];
+        for (cents, expected) in scale.cents.iter().zip(expected) {
+            assert!((cents - expected).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_scales() {
+        assert!(ScalaScale::parse("").is_err());
+        assert!(ScalaScale::parse("No count\n").is_err());
+        assert!(ScalaScale::parse("Empty\n 0\n").is_err());
+        assert!(ScalaScale::parse("Too few\n 3\n 100.0\n 2/1\n").is_err());
+        assert!(ScalaScale::parse("Bad pitch\n 1\n two\n").is_err());
+    }
+
+    #[test]
+    fn parses_keyboard_mapping() {
+        let text =
+            "! white.kbm\n 7\n 0\n 127\n 60\n 69\n 440.0\n 12\n 0\n x\n 2\n\n X\n 4\n 5\n 7\n";
+        let mapping = KeyboardMapping::parse(text).unwrap();
+
+        assert_eq!(mapping.middle_key, 60);
+        assert_eq!(mapping.reference_key, 69);
+        assert!((mapping.reference_frequency - 440.).abs() < EPSILON);
+        assert_eq!(mapping.octave_degree, 12);
+        assert_eq!(
+            mapping.map,
+            vec![Some(0), None, Some(2), None, Some(4), Some(5), Some(7)]
+        );
+    }
+
+    #[test]
+    fn missing_map_entries_are_unmapped() {
+        let mapping = KeyboardMapping::parse("3\n0\n127\n60\n60\n261.6\n0\n0\n").unwrap();
+
+        assert_eq!(mapping.map, vec![Some(0), None, None]);
+    }
+
+    #[test]
+    fn rejects_bad_keyboard_mappings() {
+        assert!(KeyboardMapping::parse("").is_err());
+        assert!(KeyboardMapping::parse("-1\n0\n127\n60\n69\n440\n0\n").is_err());
+        assert!(KeyboardMapping::parse("0\n0\n127\n60\n69\n").is_err());
+        assert!(KeyboardMapping::parse("0\n0\n127\nsixty\n69\n440\n0\n").is_err());
+        assert!(KeyboardMapping::parse("1\n0\n127\n60\n69\n440\n0\nseven\n").is_err());
+    }
+
+    #[test]
+    fn equal_temperament_needs_no_bend() {
+        let text = "12-TET\n 12\n 100.0\n 200.0\n 300.0\n 400.0\n 500.0\n 600.0\n 700.0\n 800.0\n 900.0\n 1000.0\n 1100.0\n 2/1\n";
+        let tuning = Tuning {
+            scale: ScalaScale::parse(text).unwrap(),
+            mapping: KeyboardMapping {
+                reference_key: 69,
+                reference_frequency: 440.,
+                ..default()
+            },
+        };
+
+        assert_eq!(tuning.midi_note(60, 2.), Some((60, 0)));
+        assert_eq!(tuning.midi_note(73, 2.), Some((73, 0)));
+    }
+
+    #[test]
+    fn unmapped_keys_have_no_pitch() {
+        let tuning = Tuning {
+            scale: ScalaScale::parse(include_str!("../../assets/tunings/19-edo.scl")).unwrap(),
+            mapping: KeyboardMapping {
+                map: vec![Some(0), None],
+                octave_degree: 2,
+                ..default()
+            },
+        };
+
+        assert!(tuning.frequency(60).is_some());
+        assert!(tuning.frequency(61).is_none());
+        assert!(tuning.frequency(62).is_some());
+    }
+}